@@ -1,11 +1,67 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use lib::theme::{DefaultThemes, Theme};
+use lib::debug::DebugImages;
+use lib::market::{PriceSource, PriceStat};
+use lib::ocr::OcrMode;
+use lib::theme::{ColorMetric, DefaultThemes, Theme, Themes};
+use lib::util::RewardLayout;
+use lib::wfinfo::{FetchSettings, PriceFormat, Refinement};
 use overlay::backend::OverlayMethod;
 use overlay::{OverlayAnchor, OverlayMargin};
 use serde::{Deserialize, Serialize};
 
+use crate::{OutputFormat, TriggerMode};
+use crate::cache::FetchMode;
 use crate::geometry::{Geometry, GeometryMethod};
+use crate::overlay::{OverlayBackground, OverlayLayout, OverlaySort, PricePrecision};
+
+pub fn get_default_config_dir() -> PathBuf {
+    // unwrap should never fail in this case, and if it does then its on an unsupported anyway
+    dirs::config_dir().unwrap().join("wffp")
+}
+
+pub fn get_default_config_path() -> PathBuf {
+    get_default_config_dir().join("config.toml")
+}
+
+pub fn get_default_presets_path() -> PathBuf {
+    get_default_config_dir().join("presets.toml")
+}
+
+/// Clap id of a key in a config section, which is the field name unless it was renamed
+fn arg_id<'a>(section: Option<&str>, key: &'a str) -> &'a str {
+    match (section, key) {
+        (Some("overlay"), "method") => "OVERLAY_METHOD",
+        (Some("overlay"), "output") => "OVERLAY_OUTPUT",
+        (Some("overlay"), "margin") => "margin_arg",
+        (Some("overlay"), "position") => "position_arg",
+        (Some("geometry"), "method") => "GEOMETRY_METHOD",
+        _ => key,
+    }
+}
+
+/// Copies the values in `config` into `args` unless `from_cli` is true for their arg id,
+/// tables like `[overlay]` are merged key by key
+fn merge_table(
+    args: &mut toml::Table,
+    config: toml::Table,
+    section: Option<&str>,
+    from_cli: &impl Fn(&str) -> bool,
+) {
+    for (key, value) in config {
+        match (args.get_mut(&key), value) {
+            (Some(toml::Value::Table(args)), toml::Value::Table(config)) if section.is_none() => {
+                merge_table(args, config, Some(&key), from_cli)
+            }
+            (_, value) => {
+                if !from_cli(arg_id(section, &key)) {
+                    args.insert(key, value);
+                }
+            }
+        }
+    }
+}
 
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
@@ -122,6 +178,10 @@ pub const STYLE: clap::builder::Styles = clap::builder::Styles::styled()
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[cfg_attr(
+    feature = "clap",
+    command(group(clap::ArgGroup::new("shortcut_group").multiple(true)))
+)]
 pub struct ArgShortcut {
     #[cfg_attr(
         feature = "clap",
@@ -133,10 +193,12 @@ pub struct ArgShortcut {
             default_value = "Home"
         )
     )]
-    /// Shortcut to listen too, this might be ignored on wayland environments
+    #[serde(deserialize_with = "one_or_many")]
+    /// Shortcut to listen too, can be set multiple times to activate with any of them,
+    /// this might be ignored on wayland environments
     ///
     /// depending on how GlobalShortcuts is implemented
-    pub trigger: String,
+    pub trigger: Vec<String>,
 
     #[cfg_attr(
         feature = "clap",
@@ -165,10 +227,55 @@ pub struct ArgShortcut {
     ///
     /// x11 works in xwayland mode (does not work in gamescope)
     pub method: ArgShortcutMethod,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "shortcut-mode",
+            visible_alias = "stm",
+            group = "shortcut_group",
+            default_value = "on-press"
+        )
+    )]
+    /// When the shortcut activates, holding it down only activates once
+    ///
+    /// toggle activates on press and closes on release, so the overlay shows while held
+    pub mode: TriggerMode,
+}
+
+impl ArgShortcut {
+    /// GlobalShortcuts id of each trigger, the first one keeps [ArgShortcut::id]
+    pub fn ids(&self) -> Vec<String> {
+        (0..self.trigger.len())
+            .map(|i| match i {
+                0 => self.id.clone(),
+                i => format!("{}_{i}", self.id),
+            })
+            .collect()
+    }
+}
+
+/// Configs from before [ArgShortcut::trigger] could be set multiple times have a single string
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[cfg_attr(
+    feature = "clap",
+    command(group(clap::ArgGroup::new("overlay_group").multiple(true)))
+)]
 pub struct ArgOverlay {
     #[cfg_attr(
         feature = "clap",
@@ -261,6 +368,207 @@ pub struct ArgOverlay {
     )]
     /// If set, will override the theme the overlay uses
     pub theme: Option<DefaultThemes>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-price-delta",
+            visible_alias = "opd",
+            group = "overlay_group",
+            default_value = "false"
+        )
+    )]
+    /// Shows how much the platinum changed since the last time an item was seen
+    ///
+    /// [default: false]
+    pub price_delta: bool,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-line-width",
+            visible_alias = "olw",
+            group = "overlay_group",
+            default_value = "1.0"
+        )
+    )]
+    /// Width of the separator lines based on 1080p, rounded to at least 1 pixel
+    ///
+    /// [default: 1.0]
+    pub line_width: f32,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-corner-radius",
+            visible_alias = "orad",
+            group = "overlay_group",
+            default_value = "0.0"
+        )
+    )]
+    /// Corner radius of the overlay background based on 1080p
+    ///
+    /// [default: 0.0]
+    pub corner_radius: f32,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-padding",
+            visible_alias = "opad",
+            group = "overlay_group",
+            default_value = "0.0"
+        )
+    )]
+    /// Space between the overlay edges and text based on 1080p, makes the overlay bigger
+    ///
+    /// [default: 0.0]
+    pub padding: f32,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-background",
+            visible_alias = "obg",
+            group = "overlay_group",
+            default_value = "0,0,0,160"
+        )
+    )]
+    /// Background color of the overlay, alpha is optional
+    ///
+    /// [format: #rrggbbaa or r,g,b,a] e.g. #000000 for opaque black
+    ///
+    /// [default: 0,0,0,160]
+    pub background: OverlayBackground,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-timeout",
+            visible_alias = "oto",
+            group = "overlay_group"
+        )
+    )]
+    /// Closes the overlay after this many seconds, otherwise it stays until a key is pressed
+    pub timeout: Option<f32>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-fade-in",
+            visible_alias = "ofi",
+            group = "overlay_group"
+        )
+    )]
+    /// Fades the overlay in over this many seconds
+    pub fade_in: Option<f32>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-fade-out",
+            visible_alias = "ofo",
+            group = "overlay_group"
+        )
+    )]
+    /// Fades the overlay out over this many seconds once it's closed
+    pub fade_out: Option<f32>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-interactive",
+            visible_alias = "oi",
+            group = "overlay_group",
+            action = clap::ArgAction::Set,
+            default_value = "true"
+        )
+    )]
+    /// if true, the overlay takes keyboard focus and any key closes it,
+    ///
+    /// if false, clicks and keys pass through to the game, only works on wayland
+    ///
+    /// [default: true]
+    pub interactive: bool,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-sort",
+            visible_alias = "osort",
+            group = "overlay_group",
+            default_value = "reward-order"
+        )
+    )]
+    /// Order rewards are shown in, the best reward by the same order is highlighted
+    ///
+    /// [default: reward-order]
+    pub sort: OverlaySort,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-layout",
+            visible_alias = "ol",
+            group = "overlay_group",
+            default_value = "horizontal"
+        )
+    )]
+    /// Whether rewards are shown side by side or stacked on top of each other
+    ///
+    /// [default: horizontal]
+    pub layout: OverlayLayout,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-precision",
+            visible_alias = "oprec",
+            group = "overlay_group",
+            default_value = "integer"
+        )
+    )]
+    /// Whether prices are shown rounded down to whole platinum or with one decimal,
+    /// the highlighted reward always uses the exact price
+    ///
+    /// [default: integer]
+    pub precision: PricePrecision,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-output",
+            visible_alias = "oo",
+            group = "overlay_group",
+            id = "OVERLAY_OUTPUT"
+        )
+    )]
+    /// Name of the monitor to show the overlay on, like DP-1, see --list-outputs
+    ///
+    /// only works on wayland, the compositor picks if unset
+    pub output: Option<String>,
+
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[serde(default)]
+    pub position: Option<(i32, i32)>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "overlay-position",
+            visible_alias = "op",
+            group = "overlay_group",
+            num_args = 1,
+            value_delimiter = ','
+        )
+    )]
+    #[serde(skip)]
+    /// Absolute screen position of the overlay, ignores anchor and margin
+    ///
+    /// only works on X11, wayland will use anchor and margin instead
+    ///
+    /// [format: x,y]
+    position_arg: Option<Vec<i32>>,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -321,6 +629,34 @@ pub struct ArgGeometry {
     ///
     /// [conflicts: --geometry, --geometry-command]
     geometry: Option<Vec<u32>>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, visible_alias = "rf", default_value = "false")
+    )]
+    /// Only activate when the focused window is warframe, see --window-name
+    ///
+    /// always passes with static and command geometry since they can't tell
+    ///
+    /// [default: false]
+    pub require_focus: bool,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, visible_alias = "wn", default_value = "Warframe")
+    )]
+    /// Window class or title to look for with --require-focus, ignores case
+    pub window_name: String,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, visible_alias = "cp", default_value = "0")
+    )]
+    /// Pixels to expand the captured window by on every side,
+    /// in case the geometry cuts off part of the rewards
+    ///
+    /// [default: 0]
+    pub capture_padding: u32,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -333,17 +669,238 @@ pub struct ArgMisc {
     /// overlay: use --overlay-theme
     pub detection_method: ArgDetectionMethod,
 
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            num_args = 1,
+            value_delimiter = ',',
+            conflicts_with = "detection_method"
+        )
+    )]
+    #[serde(skip)]
+    /// Detect rewards with these colors instead of a theme,
+    /// for custom UI colors that don't match any theme
+    ///
+    /// secondary is the same as primary if left out
+    ///
+    /// [format: primary,secondary] e.g. #9bdaea,#ffe7a9
+    detection_color: Option<Vec<String>>,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Normalize washed out colors of HDR screenshots before detecting the theme,
+    /// screenshots that already use the full color range are left alone
+    ///
+    /// [default: false]
+    pub hdr: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Detect the theme on every activation instead of reusing
+    /// the last theme that found rewards
+    ///
+    /// the last theme is also saved between runs, and is only reused
+    /// while a quick sample of the screen still picks it
+    ///
+    /// [default: false]
+    pub no_theme_cache: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Ignore the theme saved by the last run and detect it again
+    ///
+    /// [default: false]
+    pub redetect: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Run OCR even if the screen doesn't look like a reward screen
+    ///
+    /// [default: false]
+    pub force: bool,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// Path to a JSON list of themes in the same format as `assets/themes.json`,
+    /// replaces the default themes for detection and the overlay
+    pub themes_file: Option<PathBuf>,
+
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[serde(skip)]
+    /// Default themes or the ones from --themes-file
+    pub themes: Themes,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "rgb"))]
+    /// How pixel colors are compared to themes when detecting the theme,
+    /// lab and ciede2000 tell apart themes with similar hues better but are slower
+    ///
+    /// [default: rgb]
+    pub color_metric: ColorMetric,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "1.0"))]
+    /// Warframe's UI Scale setting, moves and resizes where rewards are read from
+    ///
+    /// to calibrate it, take a screenshot of the reward screen, measure the width
+    /// of all 4 reward boxes together in pixels and divide it by 960 * height / 1080,
+    /// --debug-dir shows whether the crops line up
+    ///
+    /// [default: 1.0]
+    pub ui_scale: f32,
+
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[serde(skip)]
+    /// Reward layout for --ui-scale
+    pub reward_layout: RewardLayout,
+
+    #[cfg_attr(feature = "clap", clap(long, short = 'r', default_value = "intact"))]
+    /// Refinement used for relic expected value with --rank-relics
+    ///
+    /// [default: intact]
+    pub refinement: Refinement,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "single-block"))]
+    /// Tesseract page segmentation mode used for reward names,
+    /// a different mode is tried if nothing matches
+    pub ocr_mode: OcrMode,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// How far a read name can be from an item name and still match,
+    /// as a fraction of each word's length
+    ///
+    /// raise for small or blurry captures, lower if rewards are misidentified
+    ///
+    /// [default: 0.33]
+    pub match_threshold: Option<f32>,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Prints how long each stage of an activation took
+    ///
+    /// [default: false]
+    pub timings: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Also shows the warframe.market price for each item, see --price-stat
+    /// for which statistic, the 48 hour median of sales by default
+    ///
+    /// needs a request per item on every activation
+    ///
+    /// [default: false]
+    pub market_prices: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "wf-info"))]
+    /// Where item prices come from, warframe.market fetches every item
+    /// the first time, which takes a few minutes
+    ///
+    /// cached for 48 hours like wfinfo prices
+    pub price_source: PriceSource,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "median48h"))]
+    /// Statistic used for --market-prices and --price-source warframe-market,
+    /// the 48 hour median of sales or a statistic of the current in game sell orders,
+    /// wfinfo prices are always an average
+    ///
+    /// [default: median48h]
+    pub price_stat: PriceStat,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// Path to the overlay font, common DejaVu and Noto paths
+    /// and then fontconfig are tried if unset or it fails to load
+    pub font: Option<PathBuf>,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// Font used for glyphs the main font doesn't have,
+    /// can be set multiple times and is tried in order
+    pub fallback_font: Vec<PathBuf>,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// Directory to write the filtered and part images of each scan to,
+    /// nothing is written if not set
+    pub debug_dir: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "0", requires = "debug_dir")
+    )]
+    /// Keeps the debug images of the last n scans in timestamped folders,
+    /// 0 overwrites the images every scan
+    ///
+    /// [default: 0]
+    ///
+    /// [requires: --debug-dir]
+    pub debug_keep: usize,
+
+    #[cfg_attr(feature = "clap", clap(long))]
+    /// Path to a JSON list of `["from", "to"]` pairs replaced in OCR output before matching,
+    /// applied before the default substitutions
+    pub substitutions: Option<PathBuf>,
+
     #[cfg_attr(feature = "clap", clap(long, short = 'p'))]
     /// Path to prices file
     ///
     /// https://api.warframestat.us/wfinfo/prices
+    ///
+    /// can also be a csv or tsv price sheet with `name,platinum,ducats` rows,
+    /// which is used as is instead of fetching prices
     pub prices: Option<PathBuf>,
 
+    #[cfg_attr(feature = "clap", clap(long, requires = "prices"))]
+    /// Format of --prices, guessed from its extension if not set
+    ///
+    /// [requires: --prices]
+    pub price_format: Option<PriceFormat>,
+
     #[cfg_attr(feature = "clap", clap(long, short = 'f', visible_alias = "fi"))]
     /// Path to filtered items file
     ///
     /// https://api.warframestat.us/wfinfo/filtered_items
     pub filtered_items: Option<PathBuf>,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Fetch prices and filtered items even if the cached ones are still fresh
+    ///
+    /// [default: false]
+    pub refresh_prices: bool,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "false", conflicts_with = "refresh_prices")
+    )]
+    /// Never fetch prices or filtered items, only use the cached ones no matter how old,
+    /// rewards show without prices if nothing is cached
+    ///
+    /// [default: false]
+    pub offline: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "2"))]
+    /// Times to retry fetching prices and filtered items after timeouts and server errors,
+    /// waiting twice as long before each retry
+    ///
+    /// [default: 2]
+    pub fetch_retries: u32,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "5"))]
+    /// Seconds before fetching prices or filtered items times out
+    ///
+    /// [default: 5]
+    pub fetch_timeout: u64,
+
+    #[cfg_attr(feature = "clap", clap(long, visible_alias = "log"))]
+    /// Path to EE.log to watch, can be set multiple times for multiple accounts
+    ///
+    /// [default: first EE.log found in %LOCALAPPDATA% or any steam library's proton prefix]
+    pub ee_log: Vec<PathBuf>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, visible_alias = "poll", default_value = "100")
+    )]
+    /// Milliseconds between polls of EE.log where file events aren't available
+    ///
+    /// [default: 100]
+    pub poll_interval: u64,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "1500"))]
+    /// Milliseconds to wait after rewards are logged before activating,
+    /// rewards logged again within this time are ignored
+    ///
+    /// [default: 1500]
+    pub debounce: u64,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
@@ -367,9 +924,39 @@ pub struct Args {
     #[serde(skip)]
     /// Activates immanently skipping the need for a shortcut
     ///
+    /// exits with 0 if rewards were found, 3 if none were found,
+    /// 4 if the theme filter found nothing, 5 if capturing failed,
+    /// 6 if warframe isn't focused, 7 if it's not on the reward screen
+    /// and 1 for any other error
+    ///
     /// [default: false]
     pub now: bool,
 
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "false", conflicts_with = "now")
+    )]
+    #[serde(skip)]
+    /// Prints the window geometry, scale, theme weights and raw OCR text
+    /// of a screenshot or --image, then exits without showing the overlay
+    ///
+    /// [default: false]
+    pub diagnose: bool,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "false", conflicts_with = "now")
+    )]
+    #[serde(skip)]
+    /// Only reacts to EE.log and prints rewards instead of showing the overlay,
+    /// no shortcut is registered and focus isn't checked, so no display server is needed
+    /// with --frame and --geometry
+    ///
+    /// prints json unless --output-format is text
+    ///
+    /// [default: false]
+    pub headless: bool,
+
     #[cfg_attr(feature = "clap", clap(long, short = 'i'))]
     /// Path to an image to be used like a screenshot of the rewards screen
     ///
@@ -379,12 +966,135 @@ pub struct Args {
     #[serde(skip)]
     pub image: Option<PathBuf>,
 
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            visible_alias = "pc",
+            default_value = "false",
+            requires = "image"
+        )
+    )]
+    #[serde(skip)]
+    /// The image is already cropped to the 4 reward boxes, like a region screenshot,
+    /// instead of being the whole screen, its width is used for the scale
+    ///
+    /// select from the left edge of the first box to the right edge of the fourth,
+    /// even with fewer rewards, and from the top of the boxes to the bottom of the names,
+    /// e.g. `grim -g "$(slurp)" rewards.png` or a rectangular region in spectacle
+    ///
+    /// [default: false]
+    ///
+    /// [requires: --image]
+    pub pre_cropped: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, short = 'F', conflicts_with = "image"))]
+    /// Path to read a frame from on each activation instead of taking a screenshot,
+    /// use `-` for stdin, can be a named pipe fed by an external capture tool
+    ///
+    /// frames are cropped with geometry options like a screenshot
+    ///
+    /// [conflicts: --image]
+    #[serde(skip)]
+    pub frame: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            visible_alias = "fs",
+            requires = "frame",
+            num_args = 1,
+            value_delimiter = ','
+        )
+    )]
+    /// If set, frames are read as raw RGBA pixels of this size instead of an image file
+    ///
+    /// [format: width,height]
+    ///
+    /// [requires: --frame]
+    #[serde(skip)]
+    frame_size: Option<Vec<u32>>,
+
     #[cfg_attr(feature = "clap", clap(long, short = 'O', visible_alias = "out"))]
     /// If set, instead of showing overlay on screen, save it as image
     ///
     /// ignores some overlay options
     #[serde(skip)]
     pub output: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, default_value = "false", requires = "output")
+    )]
+    #[serde(skip)]
+    /// Crops the saved image to the overlay's non-transparent content
+    ///
+    /// [default: false]
+    ///
+    /// [requires: --output]
+    pub trim: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "overlay"))]
+    /// json and text print found rewards to stdout instead of showing the overlay
+    ///
+    /// [default: overlay]
+    pub output_format: OutputFormat,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    /// Also copies found rewards and their prices to the clipboard,
+    /// like "Braton Prime Barrel [45p], Forma Blueprint"
+    ///
+    /// uses wl-copy on wayland, xclip or xsel on x11
+    ///
+    /// [default: false]
+    pub clipboard: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, visible_alias = "et"))]
+    /// Prints the theme with this name as json in the same format as `assets/themes.json`
+    /// and exits, includes the theme from --detection-method if it's custom
+    #[serde(skip)]
+    pub export_theme: Option<String>,
+
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, visible_alias = "rr", num_args = 1, value_delimiter = ',')
+    )]
+    #[serde(skip)]
+    /// Prints these relics ranked by the platinum an opening is worth on average
+    /// at --refinement and exits
+    ///
+    /// [format: A1,Neo N5,...] a name without a tier matches every tier that has it
+    pub rank_relics: Vec<String>,
+
+    #[cfg_attr(feature = "clap", clap(long, default_value = "false"))]
+    #[serde(skip)]
+    /// Prints the name of every wayland output for --overlay-output and exits
+    ///
+    /// [default: false]
+    pub list_outputs: bool,
+
+    #[cfg_attr(feature = "clap", clap(long, short = 'c'))]
+    #[serde(skip)]
+    /// Path to a TOML config with the same keys `RUST_LOG=debug` prints,
+    /// args given on the command line override it
+    ///
+    /// args like --image and --now can only be given on the command line
+    ///
+    /// [default: $XDG_CONFIG_HOME/wffp/config.toml]
+    pub config: Option<PathBuf>,
+
+    #[cfg_attr(feature = "clap", clap(long, visible_alias = "pre"))]
+    #[serde(skip)]
+    /// Loads overlay args saved with --save-preset, over the config
+    /// and under args given on the command line
+    pub preset: Option<String>,
+
+    #[cfg_attr(feature = "clap", clap(long, visible_alias = "sp"))]
+    #[serde(skip)]
+    /// Saves the overlay args to a preset with this name and exits,
+    /// presets are kept in $XDG_CONFIG_HOME/wffp/presets.toml
+    pub save_preset: Option<String>,
 }
 
 #[cfg(feature = "clap")]
@@ -396,12 +1106,25 @@ impl Args {
     }
 
     pub fn parse() -> Self {
-        let mut slf = <Self as clap::Parser>::parse();
+        use std::collections::HashSet;
+
+        use clap::parser::ValueSource;
+        use clap::{CommandFactory, FromArgMatches};
+
+        let matches = Self::command().get_matches();
+        let mut slf = Self::from_arg_matches(&matches)
+            .unwrap_or_else(|err| err.format(&mut Self::command()).exit());
 
         let style = STYLE.get_error();
         let e = style.render();
         let r = style.render_reset();
 
+        let from_cli = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(|id| id.as_str().to_string())
+            .collect::<HashSet<_>>();
+
         if slf.overlay.margin_arg.len() > 4 {
             Self::error(
                 clap::error::ErrorKind::TooManyValues,
@@ -424,14 +1147,274 @@ impl Args {
             );
         }
 
+        if let Some(frame_size) = &slf.frame_size
+            && frame_size.len() != 2
+        {
+            Self::error(
+                clap::error::ErrorKind::TooManyValues,
+                format!(
+                    "'{e}--frame-size{r}' must be exactly '{e}2{r}' values, got '{e}{}{r}'",
+                    frame_size.len()
+                ),
+            );
+        }
+
+        if let Some(position) = &slf.overlay.position_arg
+            && position.len() != 2
+        {
+            Self::error(
+                clap::error::ErrorKind::TooManyValues,
+                format!(
+                    "'{e}--overlay-position{r}' must be exactly '{e}2{r}' values, got '{e}{}{r}'",
+                    position.len()
+                ),
+            );
+        }
+
+        // the default config is optional, one given with --config isn't
+        let config = slf.config.clone().unwrap_or_else(get_default_config_path);
+
+        if (slf.config.is_some() || config.exists())
+            && let Err(err) = slf.load_config(&config, |id| from_cli.contains(id))
+        {
+            Self::error(
+                clap::error::ErrorKind::InvalidValue,
+                format!(
+                    "Failed to load config from '{e}{}{r}': {err:#}",
+                    config.display()
+                ),
+            );
+        }
+
+        if let Some(name) = slf.preset.clone()
+            && let Err(err) = slf.load_preset(get_default_presets_path(), &name, |id| {
+                from_cli.contains(id)
+            })
+        {
+            Self::error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("Failed to load preset '{e}{name}{r}': {err:#}"),
+            );
+        }
+
+        if let Some(colors) = &slf.misc.detection_color {
+            if colors.len() > 2 {
+                Self::error(
+                    clap::error::ErrorKind::TooManyValues,
+                    format!(
+                        "'{e}--detection-color{r}' can have at most '{e}2{r}' values, got '{e}{}{r}'",
+                        colors.len()
+                    ),
+                );
+            }
+
+            let theme = Theme::from_hex("custom", &colors[0], colors.get(1).map(String::as_str))
+                .unwrap_or_else(|err| {
+                    Self::error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!("'{e}--detection-color{r}': {err}"),
+                    )
+                });
+
+            slf.misc.detection_method = ArgDetectionMethod::Custom(theme);
+        }
+
+        if let Some(path) = &slf.misc.themes_file {
+            slf.misc.themes = Themes::from_path(path).unwrap_or_else(|err| {
+                Self::error(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!(
+                        "Failed to load themes from '{e}{}{r}': {err}",
+                        path.display()
+                    ),
+                )
+            });
+        }
+
+        slf.misc.themes = std::mem::take(&mut slf.misc.themes).with_metric(slf.misc.color_metric);
+        slf.misc.reward_layout = RewardLayout::with_ui_scale(slf.misc.ui_scale);
+        slf.misc.reward_layout.pre_cropped = slf.pre_cropped;
+
+        if slf.headless && slf.output_format == OutputFormat::Overlay {
+            slf.output_format = OutputFormat::Json;
+        }
+
         slf.geometry.method = slf.get_geometry_method();
         slf.overlay.margin = slf.get_overlay_margin();
+        slf.overlay.position = slf
+            .overlay
+            .position_arg
+            .as_ref()
+            .map(|position| (position[0], position[1]));
 
         slf
     }
 }
 
 impl Args {
+    /// Merges a TOML config with the same keys as serializing [Args] under these args,
+    /// keys are only used if `from_cli` is false for their clap id
+    ///
+    /// args skipped by serde stay as they are, so they can only be set on the command line
+    pub fn load_config(
+        &mut self,
+        path: impl AsRef<Path>,
+        from_cli: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<()> {
+        let config = toml::from_str::<toml::Table>(&std::fs::read_to_string(path)?)?;
+        self.merge_config(config, &from_cli)
+    }
+
+    /// Merges overlay args saved with [Args::save_preset] under these args like [Args::load_config]
+    pub fn load_preset(
+        &mut self,
+        path: impl AsRef<Path>,
+        name: &str,
+        from_cli: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<()> {
+        let mut presets = toml::from_str::<toml::Table>(&std::fs::read_to_string(path)?)?;
+
+        let Some(preset) = presets.remove(name) else {
+            anyhow::bail!("Preset not found: {name}");
+        };
+
+        self.merge_config(
+            toml::Table::from_iter([("overlay".into(), preset)]),
+            &from_cli,
+        )
+    }
+
+    /// Saves the overlay args as a preset with this name, replacing any preset with the same name
+    pub fn save_preset(&self, path: impl AsRef<Path>, name: &str) -> anyhow::Result<()> {
+        let path = path.as_ref();
+
+        let mut presets = match std::fs::read_to_string(path) {
+            Ok(presets) => toml::from_str::<toml::Table>(&presets)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        presets.insert(name.into(), toml::Table::try_from(&self.overlay)?.into());
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, toml::to_string_pretty(&presets)?)?;
+
+        Ok(())
+    }
+
+    fn merge_config(
+        &mut self,
+        config: toml::Table,
+        from_cli: &impl Fn(&str) -> bool,
+    ) -> anyhow::Result<()> {
+        // margin and position are only built from their args after parsing
+        self.overlay.margin = self.get_overlay_margin();
+        self.overlay.position = self
+            .overlay
+            .position_arg
+            .as_ref()
+            .map(|position| (position[0], position[1]));
+
+        let mut args = toml::Table::try_from(&*self)?;
+        merge_table(&mut args, config, None, from_cli);
+
+        let mut merged = args.try_into::<Self>()?;
+        merged.keep_cli_only(self);
+
+        let margin = merged.overlay.margin;
+        merged.overlay.margin_arg = vec![margin.top, margin.right, margin.bottom, margin.left];
+        merged.overlay.position_arg = merged.overlay.position.map(|(x, y)| vec![x, y]);
+
+        *self = merged;
+
+        Ok(())
+    }
+
+    /// Moves args skipped by serde from `args`
+    fn keep_cli_only(&mut self, args: &mut Self) {
+        self.overlay.margin_arg = std::mem::take(&mut args.overlay.margin_arg);
+        self.overlay.position_arg = std::mem::take(&mut args.overlay.position_arg);
+        self.geometry.geometry_command = std::mem::take(&mut args.geometry.geometry_command);
+        self.geometry.geometry = std::mem::take(&mut args.geometry.geometry);
+        self.misc.detection_color = std::mem::take(&mut args.misc.detection_color);
+        self.misc.themes = std::mem::take(&mut args.misc.themes);
+        self.misc.reward_layout = std::mem::take(&mut args.misc.reward_layout);
+        self.now = args.now;
+        self.diagnose = args.diagnose;
+        self.headless = args.headless;
+        self.image = std::mem::take(&mut args.image);
+        self.pre_cropped = args.pre_cropped;
+        self.frame = std::mem::take(&mut args.frame);
+        self.frame_size = std::mem::take(&mut args.frame_size);
+        self.output = std::mem::take(&mut args.output);
+        self.trim = args.trim;
+        self.export_theme = std::mem::take(&mut args.export_theme);
+        self.list_outputs = args.list_outputs;
+        self.rank_relics = std::mem::take(&mut args.rank_relics);
+        self.config = std::mem::take(&mut args.config);
+        self.preset = std::mem::take(&mut args.preset);
+        self.save_preset = std::mem::take(&mut args.save_preset);
+    }
+
+    /// Finds a theme to export by name, checking the custom detection theme first
+    pub fn find_theme(&self, name: &str) -> Option<Theme> {
+        match &self.misc.detection_method {
+            ArgDetectionMethod::Custom(theme) if theme.name == name => Some(theme.clone()),
+            _ => self.misc.themes.by_name(name).cloned(),
+        }
+    }
+
+    pub fn frame_size(&self) -> Option<[u32; 2]> {
+        self.frame_size
+            .as_ref()
+            .map(|frame_size| [frame_size[0], frame_size[1]])
+    }
+
+    /// The settings that change which theme gets detected, a theme cached under
+    /// other settings isn't used
+    pub fn theme_cache_key(&self) -> String {
+        let method = serde_json::to_string(&self.misc.detection_method).unwrap_or_default();
+
+        format!("{method} hdr={}", self.misc.hdr)
+    }
+
+    /// Where debug images are written, see --debug-dir
+    pub fn debug_images(&self) -> Option<DebugImages> {
+        self.misc.debug_dir.clone().map(|dir| DebugImages {
+            dir,
+            keep: self.misc.debug_keep,
+        })
+    }
+
+    pub fn fetch_mode(&self) -> FetchMode {
+        if self.misc.offline {
+            FetchMode::Offline
+        } else if self.misc.refresh_prices {
+            FetchMode::Refresh
+        } else {
+            FetchMode::Auto
+        }
+    }
+
+    /// Format of `--prices`, json if there isn't one
+    pub fn price_format(&self) -> PriceFormat {
+        match (self.misc.price_format, &self.misc.prices) {
+            (Some(format), _) => format,
+            (None, Some(prices)) => PriceFormat::from_path(prices),
+            (None, None) => PriceFormat::Json,
+        }
+    }
+
+    pub fn fetch_settings(&self) -> FetchSettings {
+        FetchSettings {
+            retries: self.misc.fetch_retries,
+            timeout: Duration::from_secs(self.misc.fetch_timeout),
+        }
+    }
+
     fn get_overlay_margin(&self) -> OverlayMargin {
         OverlayMargin {
             top: self.overlay.margin_arg[0],
@@ -454,3 +1437,150 @@ impl Args {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Defaults that clap would fill in, margin can't be empty
+    fn args() -> Args {
+        let mut args = Args::default();
+        args.overlay.margin_arg = vec![0; 4];
+        args
+    }
+
+    #[test]
+    fn config_fills_unset_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[overlay]\n\
+             padding = 12.0\n\
+             line_width = 3.0\n\
+             margin = [1, 2, 3, 4]\n\
+             [misc]\n\
+             ui_scale = 0.8\n",
+        )
+        .unwrap();
+
+        let mut args = args();
+        // given on the command line
+        args.overlay.padding = 20.0;
+        args.now = true;
+
+        args.load_config(&path, |id| id == "padding").unwrap();
+
+        assert_eq!(args.overlay.padding, 20.0);
+        assert_eq!(args.overlay.line_width, 3.0);
+        assert_eq!(args.overlay.margin, OverlayMargin::new(1, 2, 3, 4));
+        assert_eq!(args.overlay.margin_arg, [1, 2, 3, 4]);
+        assert_eq!(args.misc.ui_scale, 0.8);
+        // skipped by serde, so the config can't change it
+        assert!(args.now);
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn shortcut_trigger_and_mode() {
+        use clap::Parser;
+
+        let args = Args::try_parse_from([
+            "wffp",
+            "--shortcut-trigger",
+            "F1",
+            "--shortcut-mode",
+            "toggle",
+        ])
+        .unwrap();
+
+        assert_eq!(args.shortcut.trigger, ["F1"]);
+        assert_eq!(args.shortcut.mode, TriggerMode::Toggle);
+
+        let args = Args::try_parse_from(["wffp", "-S", "Home", "-S", "F2"]).unwrap();
+
+        assert_eq!(args.shortcut.trigger, ["Home", "F2"]);
+        assert_eq!(
+            args.shortcut.ids(),
+            ["wf_fissure_price_activate", "wf_fissure_price_activate_1"]
+        );
+    }
+
+    #[test]
+    fn single_shortcut_trigger_in_config() {
+        let shortcut: ArgShortcut =
+            toml::from_str("trigger = \"F1\"\nid = \"a\"\nmethod = \"X11\"\nmode = \"OnPress\"")
+                .unwrap();
+
+        assert_eq!(shortcut.trigger, ["F1"]);
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn overlay_args_combine() {
+        use clap::Parser;
+
+        let args = Args::try_parse_from([
+            "wffp",
+            "--overlay-anchor",
+            "bottom-left",
+            "--overlay-timeout",
+            "5",
+            "--overlay-fade-in",
+            "0.3",
+            "--overlay-sort",
+            "platinum",
+            "--overlay-layout",
+            "vertical",
+            "--overlay-padding",
+            "5",
+        ])
+        .unwrap();
+
+        assert_eq!(args.overlay.anchor, OverlayAnchor::BottomLeft);
+        assert_eq!(args.overlay.timeout, Some(5.0));
+        assert_eq!(args.overlay.fade_in, Some(0.3));
+        assert_eq!(args.overlay.sort, OverlaySort::Platinum);
+        assert_eq!(args.overlay.layout, OverlayLayout::Vertical);
+        assert_eq!(args.overlay.padding, 5.0);
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn preset_round_trip() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("presets.toml");
+
+        let mut saved = Args::try_parse_from([
+            "wffp",
+            "--save-preset",
+            "corner",
+            "--overlay-anchor",
+            "bottom-left",
+            "--overlay-padding",
+            "15",
+            "--overlay-margin",
+            "5,6,7,8",
+        ])
+        .unwrap();
+        saved.overlay.margin = saved.get_overlay_margin();
+        saved.save_preset(&path, "corner").unwrap();
+        args().save_preset(&path, "plain").unwrap();
+
+        let mut loaded = args();
+        loaded.load_preset(&path, "corner", |_| false).unwrap();
+
+        assert_eq!(loaded.overlay.anchor, OverlayAnchor::BottomLeft);
+        assert_eq!(loaded.overlay.padding, 15.0);
+        assert_eq!(loaded.overlay.margin, OverlayMargin::new(5, 6, 7, 8));
+        assert_eq!(loaded.overlay.margin_arg, [5, 6, 7, 8]);
+
+        let mut loaded = args();
+        loaded.load_preset(&path, "plain", |_| false).unwrap();
+        assert_eq!(loaded.overlay.anchor, OverlayAnchor::default());
+
+        assert!(args().load_preset(&path, "missing", |_| false).is_err());
+    }
+}
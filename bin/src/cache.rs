@@ -1,94 +1,456 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use lib::wfinfo::{Items, WfInfo};
-use log::debug;
+use lib::market::{PriceSource, PriceStat, WarframeMarket};
+use lib::wfinfo::{
+    FetchSettings, FilteredItems, Item, Items, PriceItem, PriceItems, Relics, WfInfo
+};
+use log::{debug, error, warn};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
+/// How long fetched data is used before fetching it again
+const CACHE_MAX_AGE: Duration = Duration::from_hours(48);
+
+/// Requests to warframe.market at once, it rate limits anything more
+const MARKET_WORKERS: usize = 3;
+
+pub fn get_default_cache_dir() -> PathBuf {
+    // unwrap should never fail in this case, and if it does then its on an unsupported anyway
+    dirs::cache_dir().unwrap().join("wffp")
+}
+
+pub fn get_default_price_history_path() -> PathBuf {
+    get_default_cache_dir().join("price_history.json")
+}
+
+pub fn get_default_theme_cache_path() -> PathBuf {
+    get_default_cache_dir().join("theme")
+}
+
+/// Name of the last theme that found rewards, saved with [save_theme_name]
+///
+/// none if it was saved with a different `key`, like another detection method
+pub fn load_theme_name(path: impl AsRef<Path>, key: &str) -> Option<String> {
+    let saved = std::fs::read_to_string(path).ok()?;
+    let (saved_key, name) = saved.split_once('\n')?;
+    let name = name.trim();
+
+    (saved_key == key && !name.is_empty()).then(|| name.to_string())
+}
+
+/// `key` is whatever changes which theme gets detected, see [crate::args::Args::theme_cache_key]
+pub fn save_theme_name(path: impl AsRef<Path>, name: &str, key: &str) -> anyhow::Result<()> {
+    let path = path.as_ref();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(path, format!("{key}\n{name}"))?;
+
+    Ok(())
+}
+
+/// How [get_items] treats cached prices and filtered items
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchMode {
+    /// Fetch when the cache is older than [CACHE_MAX_AGE]
+    #[default]
+    Auto,
+    /// Fetch regardless of how old the cache is
+    Refresh,
+    /// Never fetch, read the cache no matter how old it is
+    Offline,
+}
+
+fn read_cache<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// Reads `path` if it's younger than [CACHE_MAX_AGE], otherwise fetches it with `update`
+///
+/// a cache that fails to parse is fetched again, a fetch that fails falls back to the cache
+/// even if it's out of date, see [FetchMode] for the rest
 pub async fn get_or_update<T>(
     path: PathBuf,
+    mode: FetchMode,
     update: impl AsyncFnOnce() -> anyhow::Result<T>,
 ) -> anyhow::Result<T>
 where
     T: serde::Serialize + serde::de::DeserializeOwned,
 {
-    if !path.exists() {
-        debug!("does not exist, fetching data");
-        let t = update().await?;
+    let age = path
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
 
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(path)?;
+    match age {
+        _ if mode == FetchMode::Offline => {
+            debug!("offline, reading from cache");
+            return read_cache(&path)
+                .map_err(|err| anyhow::anyhow!("No usable cache at {}: {err}", path.display()));
+        }
+        _ if mode == FetchMode::Refresh => debug!("refresh requested, fetching data"),
+        None => debug!("does not exist, fetching data"),
+        Some(age) if age >= CACHE_MAX_AGE => debug!("out of date, fetching new data"),
+        Some(_) => match read_cache(&path) {
+            Ok(t) => {
+                debug!("up to date, reading from cache");
+                return Ok(t);
+            }
+            Err(err) => warn!("Failed to read {}, fetching data: {err}", path.display()),
+        },
+    }
 
-        serde_json::to_writer(file, &t)?;
+    let t = match update().await {
+        Ok(t) => t,
+        Err(err) => {
+            let Ok(t) = read_cache(&path) else {
+                return Err(err);
+            };
 
-        return Ok(t);
+            warn!(
+                "Failed to fetch, using old cache at {}: {err}",
+                path.display()
+            );
+            return Ok(t);
+        }
     };
 
-    let modified = path.metadata().and_then(|m| m.modified())?;
-    let now = SystemTime::now();
-    let time = now.duration_since(modified)?;
-
-    if time >= Duration::from_hours(48) {
-        debug!("out of date, fetching new data");
-
-        let t = update().await?;
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(path)?;
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
 
-        serde_json::to_writer(file, &t)?;
+    serde_json::to_writer(file, &t)?;
 
-        Ok(t)
-    } else {
-        debug!("up to date, reading from cache");
-        let file = std::fs::OpenOptions::new().read(true).open(path)?;
-
-        let t = serde_json::from_reader(file)?;
-
-        Ok(t)
-    }
+    Ok(t)
 }
 
+/// See [get_items_in], uses [get_default_cache_dir]
 pub async fn get_items(
     prices: Option<PathBuf>,
     filtered_items: Option<PathBuf>,
+    source: PriceSource,
+    stat: PriceStat,
+    mode: FetchMode,
+    fetch: FetchSettings,
 ) -> anyhow::Result<Items> {
     get_items_in(
-        // unwrap should never fail in this case, and if it does then its on an unsupported anyway
-        dirs::cache_dir().unwrap().join("wffp"),
+        get_default_cache_dir(),
         prices,
         filtered_items,
+        source,
+        stat,
+        mode,
+        fetch,
     )
     .await
 }
 
+/// Items from the prices and filtered items cached in `path`, fetching them as `mode` allows
+///
+/// `stat` is only used for warframe.market prices, see [PriceStat]
+///
+/// if there's neither a cache nor a connection this is empty rather than an error,
+/// so the overlay still shows reward names, just without prices
 pub async fn get_items_in(
     path: PathBuf,
     prices: Option<PathBuf>,
     filtered_items: Option<PathBuf>,
+    source: PriceSource,
+    stat: PriceStat,
+    mode: FetchMode,
+    fetch: FetchSettings,
 ) -> anyhow::Result<Items> {
     if !path.exists() {
         std::fs::create_dir_all(&path)?;
     }
 
-    let wfi = WfInfo::new()?;
-    let prices = prices.unwrap_or_else(|| path.join("prices.json"));
     let filtered_items = filtered_items.unwrap_or_else(|| path.join("filtered_items.json"));
+    let filtered_items = std::path::absolute(&filtered_items).unwrap_or(filtered_items);
 
-    let prices = get_or_update(prices, async || {
-        Ok(wfi.fetch_prices().await?) //
+    // each source and stat gets its own files so switching doesn't load the other's prices
+    let (prices, database) = match source {
+        PriceSource::WfInfo => (
+            prices.unwrap_or_else(|| path.join("prices.json")),
+            path.join("items.json"),
+        ),
+        PriceSource::WarframeMarket => (
+            prices.unwrap_or_else(|| path.join(format!("market_prices_{stat}.json"))),
+            path.join(format!("market_items_{stat}.json")),
+        ),
+    };
+
+    // the database remembers these, so it's rebuilt when --prices or --filtered-items change
+    let prices = std::path::absolute(&prices).unwrap_or(prices);
+    let sources: [&Path; 2] = [&prices, &filtered_items];
+
+    let use_database = match mode {
+        FetchMode::Auto => is_database_fresh(&database, &sources),
+        FetchMode::Refresh => false,
+        FetchMode::Offline => true,
+    };
+
+    if use_database {
+        match Items::load(&database, &sources) {
+            Ok(items) => {
+                debug!("up to date, reading items from database");
+                return Ok(items);
+            }
+            Err(err) => debug!("Failed to load item database, rebuilding: {err}"),
+        }
+    }
+
+    let items = match load_sources(
+        prices.clone(),
+        filtered_items.clone(),
+        source,
+        stat,
+        mode,
+        fetch,
+    )
+    .await
+    {
+        Ok(items) => items,
+        Err(err) => {
+            error!("No prices available, rewards will show without platinum or ducats: {err:#}");
+            return Ok(Items::default());
+        }
+    };
+
+    if let Err(err) = items.save(&database, &sources) {
+        warn!("Failed to save item database: {err}");
+    }
+
+    Ok(items)
+}
+
+/// Relics from the filtered items cached in [get_default_cache_dir], fetched as `mode` allows
+pub async fn get_relics(
+    filtered_items: Option<PathBuf>,
+    mode: FetchMode,
+    fetch: FetchSettings,
+) -> anyhow::Result<Relics> {
+    let path = get_default_cache_dir();
+    std::fs::create_dir_all(&path)?;
+
+    let filtered_items = filtered_items.unwrap_or_else(|| path.join("filtered_items.json"));
+    let wfi = WfInfo::with_settings(fetch)?;
+
+    let filtered_items: FilteredItems = get_or_update(filtered_items, mode, async || {
+        Ok(wfi.fetch_filtered_items().await?) //
     })
     .await?;
 
-    let filtered_items = get_or_update(filtered_items, async || {
+    Ok(filtered_items.relics)
+}
+
+async fn load_sources(
+    prices: PathBuf,
+    filtered_items: PathBuf,
+    source: PriceSource,
+    stat: PriceStat,
+    mode: FetchMode,
+    fetch: FetchSettings,
+) -> anyhow::Result<Items> {
+    let wfi = WfInfo::with_settings(fetch)?;
+
+    let filtered_items: FilteredItems = get_or_update(filtered_items, mode, async || {
         Ok(wfi.fetch_filtered_items().await?) //
     })
     .await?;
 
-    let items = Items::new(prices, filtered_items);
+    let prices = match source {
+        PriceSource::WfInfo => {
+            get_or_update(prices, mode, async || {
+                Ok(wfi.fetch_prices().await?) //
+            })
+            .await?
+        }
+        PriceSource::WarframeMarket => {
+            get_or_update(prices, mode, async || {
+                fetch_market_prices(&filtered_items, stat).await
+            })
+            .await?
+        }
+    };
 
-    Ok(items)
+    Ok(Items::new(prices, filtered_items))
+}
+
+/// `stat` of the warframe.market sell orders for every part in `filtered_items`,
+/// ranges are stored as their midpoint
+///
+/// this is a request per part, so the first fetch takes a few minutes
+async fn fetch_market_prices(
+    filtered_items: &FilteredItems,
+    stat: PriceStat,
+) -> anyhow::Result<PriceItems> {
+    let market = WarframeMarket::new()?;
+    let workers = Arc::new(Semaphore::new(MARKET_WORKERS));
+    let mut tasks = JoinSet::new();
+
+    let names = filtered_items
+        .eqmt
+        .values()
+        .flat_map(|equipment| equipment.parts.keys())
+        .cloned();
+
+    for name in names {
+        let market = market.clone();
+        let workers = workers.clone();
+
+        tasks.spawn(async move {
+            let _permit = workers.acquire_owned().await?;
+            let price = market.fetch_price(&name, stat).await;
+
+            anyhow::Ok((name, price))
+        });
+    }
+
+    let mut prices = vec![];
+
+    while let Some(task) = tasks.join_next().await {
+        match task?? {
+            (name, Ok(Some(price))) => prices.push(PriceItem {
+                name,
+                custom_avg: price.value(),
+            }),
+            (name, Ok(None)) => debug!("{name} has no warframe.market sell orders"),
+            (name, Err(err)) => warn!("Failed to fetch warframe.market price for {name}: {err}"),
+        }
+    }
+
+    Ok(prices)
+}
+
+/// true if `database` was built after `sources` were written and none of them are out of date
+fn is_database_fresh(database: &Path, sources: &[&Path]) -> bool {
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+
+    let Some(database_modified) = modified(database) else {
+        return false;
+    };
+
+    sources.iter().all(|source| {
+        modified(source).is_some_and(|source_modified| {
+            source_modified <= database_modified
+                && source_modified
+                    .elapsed()
+                    .is_ok_and(|age| age < CACHE_MAX_AGE)
+        })
+    })
+}
+
+/// Stores the current platinum of `items` by name,
+/// returns the platinum they had the last time they were seen
+pub fn update_price_history(
+    path: impl AsRef<Path>,
+    items: &[Item],
+) -> anyhow::Result<HashMap<String, f32>> {
+    let path = path.as_ref();
+
+    let mut history: HashMap<String, f32> = match std::fs::File::open(path) {
+        Ok(file) => serde_json::from_reader(file)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let previous = items
+        .iter()
+        .filter_map(|item| Some((item.name.clone(), *history.get(&item.name)?)))
+        .collect();
+
+    for item in items {
+        if let Some(platinum) = item.platinum {
+            history.insert(item.name.clone(), platinum.value());
+        }
+    }
+
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+
+    serde_json::to_writer(file, &history)?;
+
+    Ok(previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn corrupted_cache_is_fetched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prices.json");
+        std::fs::write(&path, "{ not json").unwrap();
+
+        let updated = Cell::new(false);
+        let prices = get_or_update(path.clone(), FetchMode::Auto, async || {
+            updated.set(true);
+            Ok(vec![1.0, 2.0])
+        })
+        .await
+        .unwrap();
+
+        assert!(updated.get());
+        assert_eq!(prices, [1.0, 2.0]);
+        assert_eq!(read_cache::<Vec<f32>>(&path).unwrap(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn theme_cache_is_keyed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("theme");
+
+        save_theme_name(&path, "Equinox", "\"Auto\" hdr=false").unwrap();
+
+        assert_eq!(
+            load_theme_name(&path, "\"Auto\" hdr=false").as_deref(),
+            Some("Equinox")
+        );
+        assert_eq!(load_theme_name(&path, "\"Auto\" hdr=true"), None);
+        assert_eq!(load_theme_name(&path, "\"Overlay\" hdr=false"), None);
+
+        // saved before themes were keyed
+        std::fs::write(&path, "Equinox").unwrap();
+        assert_eq!(load_theme_name(&path, "\"Auto\" hdr=false"), None);
+    }
+
+    #[tokio::test]
+    async fn offline_without_a_cache_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("does/not/exist");
+
+        for source in [PriceSource::WfInfo, PriceSource::WarframeMarket] {
+            let items = get_items_in(
+                path.clone(),
+                None,
+                None,
+                source,
+                PriceStat::default(),
+                FetchMode::Offline,
+                FetchSettings::default(),
+            )
+            .await
+            .unwrap();
+
+            assert!(items.is_empty());
+        }
+
+        // anything fetched would have been cached here
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 0);
+    }
 }
@@ -1,14 +1,170 @@
 #![allow(unused)]
 
+use std::collections::HashMap;
 use std::env;
 use std::process::Command;
 
 use serde::{Deserialize, Serialize};
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_output::{self, Transform, WlOutput};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, WEnum, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1
+};
+use zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1;
+use zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct HyprWindow {
     pub at: [u32; 2],
     pub size: [u32; 2],
+    #[serde(default)]
+    pub class: String,
+    #[serde(default)]
+    pub title: String,
+}
+
+/// Window rect as reported by sway and the Window Calls GNOME extension
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WindowRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct SwayWindowProperties {
+    #[serde(default)]
+    pub class: Option<String>,
+}
+
+/// A node from `swaymsg -t get_tree`, only the fields used to find the focused window
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwayNode {
+    pub rect: WindowRect,
+    #[serde(default)]
+    pub focused: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// wayland native windows
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// xwayland windows
+    #[serde(default)]
+    pub window_properties: Option<SwayWindowProperties>,
+    #[serde(default)]
+    pub nodes: Vec<SwayNode>,
+    #[serde(default)]
+    pub floating_nodes: Vec<SwayNode>,
+}
+
+impl SwayNode {
+    /// The deepest focused node, so a focused split container gives way to the window in it
+    pub fn find_focused(self) -> Option<SwayNode> {
+        self.find_focused_depth(0).map(|(node, _)| node)
+    }
+
+    fn find_focused_depth(mut self, depth: usize) -> Option<(SwayNode, usize)> {
+        let nodes = std::mem::take(&mut self.nodes);
+        let floating_nodes = std::mem::take(&mut self.floating_nodes);
+
+        let child = nodes
+            .into_iter()
+            .chain(floating_nodes)
+            .filter_map(|node| node.find_focused_depth(depth + 1))
+            .max_by_key(|(_, depth)| *depth);
+
+        match child {
+            Some(child) => Some(child),
+            None if self.focused => Some((self, depth)),
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HyprMonitor {
+    pub x: i32,
+    pub y: i32,
+    /// physical pixels, before `scale` and `transform`
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    #[serde(default)]
+    pub transform: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwayOutput {
+    pub rect: WindowRect,
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Bounding box of every output in compositor coordinates,
+/// which is the area a portal screenshot covers on multi-monitor setups
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OutputLayout {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OutputLayout {
+    /// Bounding box of `[x, y, width, height]` rects, None if there are none
+    pub fn from_rects(rects: impl IntoIterator<Item = [i32; 4]>) -> Option<Self> {
+        let (left, top, right, bottom) = rects.into_iter().fold(None, |bounds, [x, y, w, h]| {
+            let (left, top, right, bottom) = bounds.unwrap_or((x, y, x + w, y + h));
+            Some((left.min(x), top.min(y), right.max(x + w), bottom.max(y + h)))
+        })?;
+
+        Some(Self {
+            x: left,
+            y: top,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+
+    /// Translates absolute window `geometry` into the pixels of a screenshot of this layout
+    ///
+    /// the screenshot can be larger than the layout when outputs are scaled,
+    /// so this assumes every output has the same scale
+    pub fn to_image_space(
+        &self,
+        geometry: Geometry,
+        image_width: u32,
+        image_height: u32,
+    ) -> Geometry {
+        let scale_x = image_width as f32 / self.width.max(1) as f32;
+        let scale_y = image_height as f32 / self.height.max(1) as f32;
+
+        let x = (geometry.x as i32 - self.x) as f32 * scale_x;
+        let y = (geometry.y as i32 - self.y) as f32 * scale_y;
+
+        Geometry {
+            x: x.max(0.0).round() as u32,
+            y: y.max(0.0).round() as u32,
+            width: (geometry.width as f32 * scale_x).round() as u32,
+            height: (geometry.height as f32 * scale_y).round() as u32,
+        }
+    }
+}
+
+/// A window from the Window Calls GNOME extension's `List` method
+#[derive(Debug, Clone, Deserialize)]
+pub struct GnomeWindow {
+    pub id: u64,
+    #[serde(default)]
+    pub focus: bool,
+    #[serde(default)]
+    pub wm_class: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
@@ -48,6 +204,7 @@ impl From<HyprWindow> for Geometry {
         HyprWindow {
             at: [x, y],
             size: [width, height],
+            ..
         }: HyprWindow,
     ) -> Self {
         Self {
@@ -59,6 +216,84 @@ impl From<HyprWindow> for Geometry {
     }
 }
 
+impl From<WindowRect> for Geometry {
+    fn from(
+        WindowRect {
+            x,
+            y,
+            width,
+            height,
+        }: WindowRect,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ActiveWindow {
+    pub geometry: Geometry,
+    /// None if the method can't tell, like static or command
+    pub class: Option<String>,
+    pub title: Option<String>,
+}
+
+impl ActiveWindow {
+    /// Checks if `name` is in the class or title, ignoring case
+    ///
+    /// always true if neither are known
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+
+        if self.class.is_none() && self.title.is_none() {
+            return true;
+        }
+
+        [&self.class, &self.title]
+            .into_iter()
+            .flatten()
+            .any(|s| s.to_lowercase().contains(&name))
+    }
+}
+
+impl From<Geometry> for ActiveWindow {
+    fn from(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<HyprWindow> for ActiveWindow {
+    fn from(window: HyprWindow) -> Self {
+        Self {
+            class: Some(window.class.clone()),
+            title: Some(window.title.clone()),
+            geometry: window.into(),
+        }
+    }
+}
+
+impl From<SwayNode> for ActiveWindow {
+    fn from(node: SwayNode) -> Self {
+        let class = node
+            .app_id
+            .clone()
+            .or_else(|| node.window_properties.clone()?.class);
+
+        Self {
+            class,
+            title: node.name.clone(),
+            geometry: node.rect.into(),
+        }
+    }
+}
+
 pub fn hyprland_impl() -> anyhow::Result<HyprWindow> {
     let cmd = Command::new("hyprctl")
         .args(["activewindow", "-j"])
@@ -69,6 +304,348 @@ pub fn hyprland_impl() -> anyhow::Result<HyprWindow> {
     Ok(output)
 }
 
+pub fn hyprland_layout_impl() -> anyhow::Result<Option<OutputLayout>> {
+    let cmd = Command::new("hyprctl").args(["monitors", "-j"]).output()?;
+
+    let monitors: Vec<HyprMonitor> = serde_json::from_slice(&cmd.stdout)?;
+
+    let rects = monitors.iter().map(|monitor| {
+        let scale = monitor.scale.max(f32::EPSILON);
+        let width = (monitor.width as f32 / scale).round() as i32;
+        let height = (monitor.height as f32 / scale).round() as i32;
+
+        // odd transforms are rotated 90 or 270 degrees
+        match monitor.transform % 2 {
+            1 => [monitor.x, monitor.y, height, width],
+            _ => [monitor.x, monitor.y, width, height],
+        }
+    });
+
+    Ok(OutputLayout::from_rects(rects))
+}
+
+pub fn sway_layout_impl() -> anyhow::Result<Option<OutputLayout>> {
+    let cmd = Command::new("swaymsg")
+        .args(["-t", "get_outputs", "-r"])
+        .output()?;
+
+    let outputs: Vec<SwayOutput> = serde_json::from_slice(&cmd.stdout)?;
+
+    let rects = outputs
+        .iter()
+        .filter(|output| output.active)
+        .map(|SwayOutput { rect, .. }| {
+            [
+                rect.x as i32,
+                rect.y as i32,
+                rect.width as i32,
+                rect.height as i32,
+            ]
+        });
+
+    Ok(OutputLayout::from_rects(rects))
+}
+
+pub fn sway_impl() -> anyhow::Result<SwayNode> {
+    let cmd = Command::new("swaymsg")
+        .args(["-t", "get_tree", "-r"])
+        .output()?;
+
+    let tree: SwayNode = serde_json::from_slice(&cmd.stdout)?;
+
+    tree.find_focused()
+        .ok_or_else(|| anyhow::anyhow!("sway has no focused window"))
+}
+
+const GNOME_WINDOWS_PATH: &str = "/org/gnome/Shell/Extensions/Windows";
+const GNOME_WINDOWS_INTERFACE: &str = "org.gnome.Shell.Extensions.Windows";
+
+/// Calls a Window Calls extension method with gdbus, returning the JSON string it replies with
+fn gnome_windows_call(method: &str, args: &[&str]) -> anyhow::Result<String> {
+    let cmd = Command::new("gdbus")
+        .args(["call", "--session", "--dest", "org.gnome.Shell"])
+        .args(["--object-path", GNOME_WINDOWS_PATH])
+        .arg("--method")
+        .arg(format!("{GNOME_WINDOWS_INTERFACE}.{method}"))
+        .args(args)
+        .output()?;
+
+    if !cmd.status.success() {
+        let stderr = String::from_utf8_lossy(&cmd.stderr);
+
+        return Err(anyhow::anyhow!(
+            "Couldn't call {GNOME_WINDOWS_INTERFACE}.{method}, \
+            install the Window Calls GNOME extension or use static or command method: {}",
+            stderr.trim()
+        ));
+    }
+
+    // gdbus prints the reply as a GVariant tuple like `('[...]',)`
+    let stdout = String::from_utf8(cmd.stdout)?;
+    let quoted = stdout
+        .trim()
+        .strip_prefix("('")
+        .and_then(|s| s.strip_suffix("',)"))
+        .ok_or_else(|| anyhow::anyhow!("Unexpected gdbus output: {stdout}"))?;
+
+    let mut json = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => json.extend(chars.next()),
+            c => json.push(c),
+        }
+    }
+
+    Ok(json)
+}
+
+pub fn gnome_impl() -> anyhow::Result<ActiveWindow> {
+    let windows: Vec<GnomeWindow> = serde_json::from_str(&gnome_windows_call("List", &[])?)?;
+
+    let window = windows
+        .into_iter()
+        .find(|window| window.focus)
+        .ok_or_else(|| anyhow::anyhow!("GNOME has no focused window"))?;
+
+    let rect: WindowRect = serde_json::from_str(&gnome_windows_call(
+        "GetFrameRect",
+        &[&window.id.to_string()],
+    )?)?;
+
+    Ok(ActiveWindow {
+        geometry: rect.into(),
+        class: window.wm_class,
+        title: window.title,
+    })
+}
+
+/// An output from wl_output, in compositor coordinates
+#[derive(Debug, Default, Clone, Copy)]
+struct WlrOutput {
+    x: i32,
+    y: i32,
+    /// physical pixels of the current mode, before `scale` and `transform`
+    width: i32,
+    height: i32,
+    scale: i32,
+    rotated: bool,
+}
+
+impl WlrOutput {
+    fn rect(&self) -> [i32; 4] {
+        let scale = self.scale.max(1);
+        let (width, height) = (self.width / scale, self.height / scale);
+
+        match self.rotated {
+            true => [self.x, self.y, height, width],
+            false => [self.x, self.y, width, height],
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct WlrToplevel {
+    title: Option<String>,
+    app_id: Option<String>,
+    outputs: Vec<ObjectId>,
+    activated: bool,
+}
+
+/// Outputs and toplevels from wlr foreign toplevel management,
+/// which niri, river and most wlroots compositors implement
+#[derive(Debug, Default)]
+struct WlrState {
+    outputs: HashMap<ObjectId, WlrOutput>,
+    toplevels: HashMap<ObjectId, WlrToplevel>,
+}
+
+/// wl_output version with the scale event
+const WL_OUTPUT_VERSION: u32 = 2;
+
+fn wlr_state() -> anyhow::Result<WlrState> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut event_queue) = registry_queue_init::<WlrState>(&conn)?;
+    let qh = event_queue.handle();
+
+    globals.contents().with_list(|list| {
+        for global in list
+            .iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+        {
+            let version = global.version.min(WL_OUTPUT_VERSION);
+            globals
+                .registry()
+                .bind::<WlOutput, _, _>(global.name, version, &qh, ());
+        }
+    });
+
+    let _manager = globals
+        .bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ())
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "Compositor doesn't support wlr foreign toplevel management, \
+                try static or command method: {err}"
+            )
+        })?;
+
+    let mut state = WlrState::default();
+
+    // first for outputs and toplevels, then for the toplevels' properties
+    event_queue.roundtrip(&mut state)?;
+    event_queue.roundtrip(&mut state)?;
+
+    Ok(state)
+}
+
+/// The protocol doesn't expose window geometry, so this is the output the focused window is on,
+/// which is right for warframe in fullscreen or borderless
+pub fn wlr_impl() -> anyhow::Result<ActiveWindow> {
+    let state = wlr_state()?;
+
+    let toplevel = state
+        .toplevels
+        .into_values()
+        .find(|toplevel| toplevel.activated)
+        .ok_or_else(|| anyhow::anyhow!("Compositor has no focused window"))?;
+
+    let [x, y, width, height] = toplevel
+        .outputs
+        .iter()
+        .find_map(|output| state.outputs.get(output))
+        .ok_or_else(|| anyhow::anyhow!("Focused window isn't on any output"))?
+        .rect();
+
+    Ok(ActiveWindow {
+        geometry: Geometry {
+            x: x.max(0) as u32,
+            y: y.max(0) as u32,
+            width: width as u32,
+            height: height as u32,
+        },
+        class: toplevel.app_id,
+        title: toplevel.title,
+    })
+}
+
+pub fn wlr_layout_impl() -> anyhow::Result<Option<OutputLayout>> {
+    let state = wlr_state()?;
+
+    Ok(OutputLayout::from_rects(
+        state.outputs.values().map(WlrOutput::rect),
+    ))
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WlrState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _globals: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        proxy: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let output = state.outputs.entry(proxy.id()).or_default();
+
+        match event {
+            wl_output::Event::Geometry {
+                x, y, transform, ..
+            } => {
+                output.x = x;
+                output.y = y;
+                output.rotated = matches!(
+                    transform,
+                    WEnum::Value(
+                        Transform::_90
+                            | Transform::_270
+                            | Transform::Flipped90
+                            | Transform::Flipped270
+                    )
+                );
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.width = width;
+                output.height = height;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrForeignToplevelManagerV1,
+        event: <ZwlrForeignToplevelManagerV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state
+                .toplevels
+                .insert(toplevel.id(), WlrToplevel::default());
+        }
+    }
+
+    event_created_child!(WlrState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WlrState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrForeignToplevelHandleV1,
+        event: <ZwlrForeignToplevelHandleV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::{Event, State};
+
+        if let Event::Closed = event {
+            state.toplevels.remove(&proxy.id());
+            return;
+        }
+
+        let toplevel = state.toplevels.entry(proxy.id()).or_default();
+
+        match event {
+            Event::Title { title } => toplevel.title = Some(title),
+            Event::AppId { app_id } => toplevel.app_id = Some(app_id),
+            Event::OutputEnter { output } => toplevel.outputs.push(output.id()),
+            Event::OutputLeave { output } => toplevel.outputs.retain(|id| *id != output.id()),
+            Event::State { state } => {
+                toplevel.activated = state
+                    .chunks_exact(4)
+                    .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .any(|value| value == State::Activated as u32);
+            }
+            _ => {}
+        }
+    }
+}
+
 pub fn custom_impl(cmd: String) -> anyhow::Result<Geometry> {
     let words = shell_words::split(cmd.as_str())?;
     let cmd = &words[0];
@@ -104,6 +681,8 @@ pub enum GeometryMethod {
     Sway,
     Kde,
     Gnome,
+    /// Any compositor with wlr foreign toplevel management, like niri or river
+    Wlr,
     Unknown,
     #[default]
     Auto,
@@ -129,32 +708,93 @@ impl GeometryMethod {
             "sway" => Self::Sway,
             "kde" => Self::Kde,
             "gnome" => Self::Gnome,
+            "niri" | "river" | "cosmic" => Self::Wlr,
             _ => Self::Unknown,
         }
     }
 
     pub fn check_unsupported(&self) -> anyhow::Result<()> {
-        if matches!(self, Self::Hyprland | Self::Command(_) | Self::Static(_)) {
+        if matches!(
+            self,
+            Self::Hyprland
+                | Self::Sway
+                | Self::Gnome
+                | Self::Wlr
+                | Self::Command(_)
+                | Self::Static(_)
+        ) {
             return Ok(());
         };
 
         Err(anyhow::anyhow!(
-            "Only hyprland is supported currently, try static or command method"
+            "Only hyprland, sway, gnome and wlr are supported currently, \
+            try static or command method"
         ))
     }
 
+    /// None if the method doesn't know the outputs, like gnome, static or command,
+    /// in which case window geometry is used as screenshot pixels as is
+    pub fn get_output_layout(&self) -> anyhow::Result<Option<OutputLayout>> {
+        match self {
+            Self::Auto => Self::detect().get_output_layout(),
+            Self::Hyprland => hyprland_layout_impl(),
+            Self::Sway => sway_layout_impl(),
+            Self::Wlr => wlr_layout_impl(),
+            _ => Ok(None),
+        }
+    }
+
     pub fn get_active_window_geometry(self) -> anyhow::Result<Geometry> {
+        self.get_active_window().map(|window| window.geometry)
+    }
+
+    pub fn get_active_window(self) -> anyhow::Result<ActiveWindow> {
         match self {
-            Self::Auto => Self::detect().get_active_window_geometry(),
+            Self::Auto => Self::detect().get_active_window(),
             Self::Hyprland => hyprland_impl().map(Into::into),
-            Self::Sway => Err(anyhow::anyhow!("Currently Unsupported")),
+            Self::Sway => sway_impl().map(Into::into),
             Self::Kde => Err(anyhow::anyhow!("Currently Unsupported")),
-            Self::Gnome => Err(anyhow::anyhow!("Currently Unsupported")),
+            Self::Gnome => gnome_impl(),
+            Self::Wlr => wlr_impl(),
             Self::Unknown => Err(anyhow::anyhow!(
                 "Unknown desktop, try static or command method"
             )),
             Self::Static(w) => Ok(w.into()),
-            Self::Command(cmd) => custom_impl(cmd),
+            Self::Command(cmd) => custom_impl(cmd).map(Into::into),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_outputs_with_offset() {
+        // a 1080p output left of a 1440p one, lower down and not starting at 0
+        let layout =
+            OutputLayout::from_rects([[1000, 360, 1920, 1080], [2920, 0, 2560, 1440]]).unwrap();
+
+        assert_eq!(
+            layout,
+            OutputLayout {
+                x: 1000,
+                y: 0,
+                width: 4480,
+                height: 1440,
+            }
+        );
+
+        let left = Geometry::from([1000, 360, 1920, 1080]);
+        let right = Geometry::from([2920, 0, 2560, 1440]);
+
+        let to_image = |geometry, scale: u32| {
+            <[u32; 4]>::from(layout.to_image_space(geometry, 4480 * scale, 1440 * scale))
+        };
+
+        assert_eq!(to_image(left, 1), [0, 360, 1920, 1080]);
+        assert_eq!(to_image(right, 1), [1920, 0, 2560, 1440]);
+        // outputs scaled by 2 make a screenshot twice as large as the layout
+        assert_eq!(to_image(right, 2), [3840, 0, 5120, 2880]);
+    }
+}
@@ -5,26 +5,235 @@ mod util;
 pub mod watcher;
 pub mod args;
 
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 use image::DynamicImage;
-use lib::ocr::reward_image_to_items;
-use lib::theme::Theme;
-use lib::util::{PIXEL_MARGIN_TOP, PIXEL_REWARD_HEIGHT, PIXEL_SINGLE_REWARD_WIDTH, get_scale};
-use lib::wfinfo::Items;
-use log::debug;
+use lib::debug::DebugImages;
+use lib::market::{PriceStat, WarframeMarket};
+use lib::ocr::{OcrMode, Substitutions, is_reward_screen, reward_image_to_items};
+use lib::theme::{DEFAULT_THEMES, Theme, ThemeSelection, Themes};
+use lib::timings::Timings;
+use lib::util::{PIXEL_MARGIN_TOP, RewardLayout};
+use lib::wfinfo::{Item, Items};
+use log::{debug, warn};
 use overlay::backend::{OverlayBackend, OverlayMethod, get_backend};
 use overlay::{OverlayAnchor, OverlayConf, OverlayMargin};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::geometry::GeometryMethod;
-use crate::overlay::Overlay;
+use crate::cache::update_price_history;
+use crate::geometry::{Geometry, GeometryMethod};
+use crate::overlay::{
+    Overlay, OverlayBackground, OverlayLayout, OverlaySort, PricePrecision, UNKNOWN_ITEM
+};
+
+/// Errors from scanning rewards and showing the overlay,
+/// converts into [anyhow::Error] like any other error
+#[derive(Error, Debug)]
+pub enum BinError {
+    #[error("Overlay backend not found for {0:?}")]
+    BackendNotFound(OverlayMethod),
+    /// Taking the screenshot or getting the window geometry failed
+    #[error("Failed to capture image")]
+    CaptureFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Theme with this name was detected but nothing passed its filter, see [ScanResult]
+    #[error("Nothing passed the filter of theme {0}")]
+    NoForeground(String),
+    #[error("Rewards didn't match any items")]
+    NoMatch,
+    #[error("Not a reward screen")]
+    NotRewardScreen,
+    /// The reward boxes of the window at this geometry are cut off by the edge of the capture
+    #[error("Reward area of the window at {0:?} is outside the captured image")]
+    RewardAreaClipped(Geometry),
+    /// Theme detection or OCR failed, like [lib::Error::UnknownTheme]
+    #[error(transparent)]
+    Recognition(#[from] lib::Error),
+    #[error(transparent)]
+    Overlay(#[from] overlay::Error),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+}
+
+/// How found rewards are shown
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    #[default]
+    Overlay,
+    /// Prints a JSON list of rewards to stdout, unknown rewards are null
+    Json,
+    /// Prints a row per reward to stdout
+    Text,
+}
+
+/// Reward as printed by [OutputFormat::Json]
+#[derive(Debug, Clone, Serialize)]
+struct RewardOutput<'a> {
+    name: &'a str,
+    platinum: Option<f32>,
+    market_platinum: Option<f32>,
+    set_platinum: Option<f32>,
+    ducats: Option<usize>,
+    vaulted: bool,
+    tradeable: bool,
+}
+
+impl<'a> From<&'a Item> for RewardOutput<'a> {
+    fn from(item: &'a Item) -> Self {
+        Self {
+            name: &item.name,
+            platinum: item.platinum.map(|p| p.value()),
+            market_platinum: item.market_platinum.map(|p| p.value()),
+            set_platinum: item.set_platinum.map(|p| p.value()),
+            ducats: item.ducats,
+            vaulted: item.vaulted,
+            tradeable: item.is_tradeable(),
+        }
+    }
+}
+
+/// Prints `items` to stdout in `format`, does nothing for [OutputFormat::Overlay]
+pub fn print_rewards(items: &[Option<Item>], format: OutputFormat) -> Result<(), BinError> {
+    match format {
+        OutputFormat::Overlay => {}
+        OutputFormat::Json => {
+            let rewards = items
+                .iter()
+                .map(|item| item.as_ref().map(RewardOutput::from))
+                .collect::<Vec<_>>();
+
+            println!("{}", serde_json::to_string(&rewards)?);
+        }
+        OutputFormat::Text => {
+            let width = items
+                .iter()
+                .map(|item| item.as_ref().map_or(UNKNOWN_ITEM, |item| &item.name).len())
+                .max()
+                .unwrap_or_default();
+
+            for item in items {
+                let Some(item) = item else {
+                    println!("{UNKNOWN_ITEM}");
+                    continue;
+                };
+
+                if !item.is_tradeable() {
+                    println!("{:width$}  not tradeable", item.name);
+                    continue;
+                }
+
+                let platinum = item.platinum.map(|p| p.to_string()).unwrap_or_default();
+                let ducats = item.ducats.map(|d| d.to_string()).unwrap_or_default();
+                let vaulted = if item.vaulted { "vaulted" } else { "" };
+
+                println!(
+                    "{:width$}  {platinum:>6}p  {ducats:>3}d  {vaulted}",
+                    item.name
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// When a held shortcut runs its callback, holding the key never runs it more than once
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum TriggerMode {
+    /// When the key is pressed
+    #[default]
+    OnPress,
+    /// When the key is released
+    OnRelease,
+    /// When the key is pressed and again when it's released,
+    /// the overlay toggles on each so it stays open while the key is held
+    Toggle,
+}
+
+impl TriggerMode {
+    fn on_press(self) -> bool {
+        matches!(self, Self::OnPress | Self::Toggle)
+    }
+
+    fn on_release(self) -> bool {
+        matches!(self, Self::OnRelease | Self::Toggle)
+    }
+}
+
+/// Tradeable rewards with their prices for pasting into trade chat,
+/// like "Braton Prime Barrel [45p], Forma Blueprint"
+pub fn clipboard_summary(items: &[Option<Item>]) -> String {
+    items
+        .iter()
+        .flatten()
+        .map(|item| match item.platinum {
+            Some(platinum) if item.is_tradeable() => format!("{} [{platinum}p]", item.name),
+            _ => item.name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Copies `text` with the first clipboard tool found, wl-copy on wayland, xclip or xsel on x11
+pub fn copy_to_clipboard(text: &str) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let wayland: &[&[&str]] = &[&["wl-copy"]];
+    let x11: &[&[&str]] = &[
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+
+    let tools = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        [wayland, x11].concat()
+    } else {
+        x11.to_vec()
+    };
+
+    for tool in tools {
+        let Ok(mut child) = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .spawn()
+        else {
+            debug!("{} not found", tool[0]);
+            continue;
+        };
+
+        // the tools keep running in the background to serve the clipboard once stdin closes
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Couldn't write to {}", tool[0]))?
+            .write_all(text.as_bytes())?;
+
+        let status = child.wait()?;
+
+        if !status.success() {
+            return Err(anyhow::anyhow!("{} exited with {status}", tool[0]));
+        }
+
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "No clipboard tool found, install wl-clipboard on wayland or xclip on x11"
+    ))
+}
 
 #[derive(Debug, Clone)]
 pub struct ShortcutSettings<'a> {
     pub id: &'a str,
     pub preferred_trigger: &'a str,
+    pub mode: TriggerMode,
 }
 
 impl Default for ShortcutSettings<'_> {
@@ -32,6 +241,7 @@ impl Default for ShortcutSettings<'_> {
         Self {
             id: "wf_fissure_price_activate",
             preferred_trigger: "Home",
+            mode: TriggerMode::default(),
         }
     }
 }
@@ -130,10 +340,37 @@ pub fn x11_shortcut_parser(
     }
 }
 
+/// A shortcut and the callback it runs, see [x11_shortcuts] and [portal_shortcuts]
+pub struct ShortcutBinding<'a> {
+    pub id: &'a str,
+    pub description: &'a str,
+    pub preferred_trigger: &'a str,
+    pub mode: TriggerMode,
+    pub callback: Box<dyn Fn() + 'a>,
+}
+
+impl<'a> ShortcutBinding<'a> {
+    pub fn new(settings: ShortcutSettings<'a>, callback: impl Fn() + 'a) -> Self {
+        Self {
+            id: settings.id,
+            description: "Activates this program to screenshot warframe and show overlay",
+            preferred_trigger: settings.preferred_trigger,
+            mode: settings.mode,
+            callback: Box::new(callback),
+        }
+    }
+}
+
 pub async fn x11_shortcut(
     settings: ShortcutSettings<'_>,
     callback: impl Fn(),
 ) -> anyhow::Result<()> {
+    x11_shortcuts(vec![ShortcutBinding::new(settings, callback)]).await
+}
+
+/// Grabs every binding's key, pressing or releasing it runs the binding
+/// with the same keycode and modifiers depending on its [TriggerMode]
+pub async fn x11_shortcuts(bindings: Vec<ShortcutBinding<'_>>) -> anyhow::Result<()> {
     use x11rb::connection::Connection;
     use x11rb::protocol::xproto::*;
     use x11rb::rust_connection::RustConnection;
@@ -147,33 +384,92 @@ pub async fn x11_shortcut(
         .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
         .reply()?;
 
-    let (modmask, keysym) = x11_shortcut_parser(settings.preferred_trigger)?;
-
-    let keycode = mappings
-        .keysyms
-        .chunks(mappings.keysyms_per_keycode as usize)
-        .enumerate()
-        .find(|(_, keysyms)| keysyms.contains(&keysym.raw()))
-        .map(|(i, _)| i as u8 + min_keycode)
-        .ok_or_else(|| anyhow::anyhow!("Couldn't find keycode for {keysym:?}"))?;
-
-    conn.grab_key(
-        false,
-        screen.root,
-        modmask,
-        keycode,
-        GrabMode::ASYNC,
-        GrabMode::ASYNC,
-    )?;
+    let mut grabs = vec![];
+
+    for binding in &bindings {
+        let (modmask, keysym) = x11_shortcut_parser(binding.preferred_trigger)?;
+
+        let keycode = mappings
+            .keysyms
+            .chunks(mappings.keysyms_per_keycode as usize)
+            .enumerate()
+            .find(|(_, keysyms)| keysyms.contains(&keysym.raw()))
+            .map(|(i, _)| i as u8 + min_keycode)
+            .ok_or_else(|| anyhow::anyhow!("Couldn't find keycode for {keysym:?}"))?;
+
+        conn.grab_key(
+            false,
+            screen.root,
+            modmask,
+            keycode,
+            GrabMode::ASYNC,
+            GrabMode::ASYNC,
+        )?;
+
+        debug!(
+            "Shortcut grabbed: id = {}, keycode = {keycode}, modmask = {modmask:?}",
+            binding.id
+        );
+
+        grabs.push((keycode, u16::from(modmask), binding));
+    }
 
     conn.flush()?;
 
+    // only modifier bits, the rest of the state is mouse buttons
+    let modifiers = 0xff;
+
+    // keycode -> binding it was pressed for, the modifiers might be released first
+    let mut held = HashMap::new();
+    let mut pending = None;
+
     loop {
-        let event = conn.wait_for_event()?;
+        let event = match pending.take() {
+            Some(event) => event,
+            None => conn.wait_for_event()?,
+        };
 
-        if let x11rb::protocol::Event::KeyPress(_) = event {
-            callback();
-        }
+        let (event, pressed) = match event {
+            x11rb::protocol::Event::KeyPress(event) => (event, true),
+            x11rb::protocol::Event::KeyRelease(event) => {
+                // auto repeat sends a release and a press at the same time while the key is held
+                match conn.poll_for_event()? {
+                    Some(x11rb::protocol::Event::KeyPress(next))
+                        if next.detail == event.detail && next.time == event.time =>
+                    {
+                        continue;
+                    }
+                    next => pending = next,
+                }
+
+                (event, false)
+            }
+            _ => continue,
+        };
+
+        let binding = if pressed {
+            let state = u16::from(event.state) & modifiers;
+            let Some((keycode, _, binding)) = grabs
+                .iter()
+                .find(|(keycode, modmask, _)| *keycode == event.detail && *modmask == state)
+            else {
+                continue;
+            };
+
+            // detectable auto repeat only sends presses
+            if held.insert(*keycode, *binding).is_some() || !binding.mode.on_press() {
+                continue;
+            }
+
+            binding
+        } else {
+            match held.remove(&event.detail) {
+                Some(binding) if binding.mode.on_release() => binding,
+                _ => continue,
+            }
+        };
+
+        (binding.callback)();
     }
 }
 
@@ -181,19 +477,27 @@ pub async fn portal_shortcut(
     settings: ShortcutSettings<'_>,
     callback: impl Fn(),
 ) -> anyhow::Result<()> {
+    portal_shortcuts(vec![ShortcutBinding::new(settings, callback)]).await
+}
+
+/// Binds every shortcut in one session, activations and deactivations run the binding
+/// with the same id depending on its [TriggerMode]
+pub async fn portal_shortcuts(bindings: Vec<ShortcutBinding<'_>>) -> anyhow::Result<()> {
     use ashpd::desktop::global_shortcuts::{GlobalShortcuts, NewShortcut};
     use tokio_stream::StreamExt;
 
     let portal = GlobalShortcuts::new().await?;
     let session = portal.create_session().await?;
 
-    let shortcut = NewShortcut::new(
-        settings.id,
-        "Activates this program to screenshot warframe and show overlay",
-    )
-    .preferred_trigger(settings.preferred_trigger);
+    let shortcuts = bindings
+        .iter()
+        .map(|binding| {
+            NewShortcut::new(binding.id, binding.description)
+                .preferred_trigger(binding.preferred_trigger)
+        })
+        .collect::<Vec<_>>();
 
-    let request = portal.bind_shortcuts(&session, &[shortcut], None).await?;
+    let request = portal.bind_shortcuts(&session, &shortcuts, None).await?;
 
     let response = request.response()?;
 
@@ -206,16 +510,53 @@ pub async fn portal_shortcut(
         );
     }
 
-    let mut activated = portal.receive_activated().await?;
+    let activated = portal
+        .receive_activated()
+        .await?
+        .map(|activated| (activated.shortcut_id().to_string(), true));
+    let deactivated = portal
+        .receive_deactivated()
+        .await?
+        .map(|deactivated| (deactivated.shortcut_id().to_string(), false));
+
+    // the portal only sends these once per press and release, so there's no repeat to ignore
+    let mut events = activated.merge(deactivated);
 
-    while activated.next().await.is_some() {
-        callback();
+    while let Some((id, pressed)) = events.next().await {
+        let Some(binding) = bindings.iter().find(|binding| binding.id == id) else {
+            debug!("Unknown shortcut activated: {id}");
+            continue;
+        };
+
+        let trigger = if pressed {
+            binding.mode.on_press()
+        } else {
+            binding.mode.on_release()
+        };
+
+        if trigger {
+            (binding.callback)();
+        }
     }
 
     Ok(())
 }
 
-pub async fn take_screenshot(method: GeometryMethod) -> anyhow::Result<DynamicImage> {
+/// `padding` expands the window geometry, see [crop_to_geometry]
+pub async fn take_screenshot(
+    method: GeometryMethod,
+    padding: u32,
+    layout: &RewardLayout,
+) -> Result<DynamicImage, BinError> {
+    let (image, geometry) = screenshot(method)
+        .await
+        .map_err(|err| BinError::CaptureFailed(err.into()))?;
+
+    crop_to_geometry(image, geometry, padding, layout)
+}
+
+/// Screenshot of every output and the active window's geometry in it
+async fn screenshot(method: GeometryMethod) -> anyhow::Result<(DynamicImage, Geometry)> {
     use ashpd::desktop::screenshot::Screenshot;
 
     let ss = Screenshot::request()
@@ -226,60 +567,376 @@ pub async fn take_screenshot(method: GeometryMethod) -> anyhow::Result<DynamicIm
 
     let ss = ss.response()?;
     let image = image::open(ss.uri().path())?;
-    let geometry = method.get_active_window_geometry()?;
+    let layout = method.get_output_layout()?;
+    let mut geometry = method.get_active_window_geometry()?;
+
+    // the screenshot spans every output, window geometry is in compositor coordinates
+    if let Some(layout) = layout {
+        geometry = layout.to_image_space(geometry, image.width(), image.height());
+    }
+
+    Ok((image, geometry))
+}
+
+/// Crops to `geometry` expanded by `padding` on every side, clamped to the image
+///
+/// only the padding may be cut off, fails with [BinError::RewardAreaClipped]
+/// if the reward boxes of `layout` in the window don't fit in the crop
+pub fn crop_to_geometry(
+    image: DynamicImage,
+    geometry: Geometry,
+    padding: u32,
+    layout: &RewardLayout,
+) -> Result<DynamicImage, BinError> {
     let [x, y, w, h] = geometry.into();
 
-    let image = image.crop_imm(x, y, w, h);
+    let left = x.saturating_sub(padding);
+    let top = y.saturating_sub(padding);
+    let right = x
+        .saturating_add(w)
+        .saturating_add(padding)
+        .min(image.width());
+    let bottom = y
+        .saturating_add(h)
+        .saturating_add(padding)
+        .min(image.height());
+
+    let [area_x, area_y, area_w, area_h] = layout.area(w, h, layout.scale_for(w, h));
+    let area_left = x as f32 + area_x;
+    let area_top = y as f32 + area_y;
+
+    if area_left < left as f32
+        || area_top < top as f32
+        || area_left + area_w > right as f32
+        || area_top + area_h > bottom as f32
+    {
+        return Err(BinError::RewardAreaClipped(geometry));
+    }
+
+    let width = right.saturating_sub(left);
+    let height = bottom.saturating_sub(top);
 
-    Ok(image)
+    Ok(image.crop_imm(left, top, width, height))
 }
 
-pub async fn extract_reward_image<'a>(
+/// Builds a frame from raw RGBA pixels, like the ones an external capture tool outputs
+pub fn frame_from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> anyhow::Result<DynamicImage> {
+    let image = image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("Frame does not match size {width}x{height}"))?;
+
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Reads a single frame from a file, named pipe or stdin (`-`)
+///
+/// if `size` is set, exactly `width * height * 4` bytes of raw RGBA are read,
+/// so consecutive frames can be streamed through the same pipe,
+/// otherwise the whole input is decoded as an image file
+pub fn read_frame(path: &Path, size: Option<[u32; 2]>) -> anyhow::Result<DynamicImage> {
+    use std::io::Read;
+
+    let mut reader: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::fs::File::open(path)?)
+    };
+
+    match size {
+        Some([width, height]) => {
+            let Some(len) = (width as usize)
+                .checked_mul(height as usize)
+                .and_then(|pixels| pixels.checked_mul(4))
+            else {
+                anyhow::bail!("Frame size {width}x{height} is too large");
+            };
+
+            let mut pixels = vec![0; len];
+            reader.read_exact(&mut pixels)?;
+
+            frame_from_rgba(width, height, pixels)
+        }
+        None => {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes)?;
+
+            Ok(image::load_from_memory(&bytes)?)
+        }
+    }
+}
+
+/// Outcome of a scan, separates a theme that doesn't fit from not being on a reward screen
+#[derive(Debug)]
+pub enum ScanResult<T> {
+    Found(T),
+    /// Theme with this name was detected but nothing passed its filter,
+    /// its thresholds likely need tuning
+    NoForeground(String),
+    /// Rewards were read but didn't match any items
+    NoMatch,
+    /// The image doesn't look like a reward screen, so OCR was skipped, see [is_reward_screen]
+    NotRewardScreen,
+}
+
+impl<T> ScanResult<T> {
+    pub fn found(self) -> Option<T> {
+        match self {
+            ScanResult::Found(found) => Some(found),
+            _ => None,
+        }
+    }
+
+    pub fn is_found(&self) -> bool {
+        matches!(self, ScanResult::Found(_))
+    }
+
+    /// The found value, or why nothing was found as an error
+    pub fn into_result(self) -> Result<T, BinError> {
+        match self {
+            ScanResult::Found(found) => Ok(found),
+            ScanResult::NoForeground(theme) => Err(BinError::NoForeground(theme)),
+            ScanResult::NoMatch => Err(BinError::NoMatch),
+            ScanResult::NotRewardScreen => Err(BinError::NotRewardScreen),
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ScanResult<U> {
+        match self {
+            ScanResult::Found(found) => ScanResult::Found(f(found)),
+            ScanResult::NoForeground(theme) => ScanResult::NoForeground(theme),
+            ScanResult::NoMatch => ScanResult::NoMatch,
+            ScanResult::NotRewardScreen => ScanResult::NotRewardScreen,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn scan<'a>(
     image: DynamicImage,
     items: &Items,
-    theme: Option<&'a Theme>,
-) -> anyhow::Result<Option<Overlay<'a>>> {
-    let scale = get_scale(&image)?;
-    let (items, theme) = reward_image_to_items(items, image, theme)?;
+    selection: ThemeSelection<'a>,
+    layout: &RewardLayout,
+    mode: OcrMode,
+    substitutions: &Substitutions,
+    debug_images: Option<&DebugImages>,
+    timings: &mut Timings,
+) -> Result<ScanResult<Overlay<'a>>, BinError> {
+    let scale = layout.scale(&image)?;
+    let (items, theme) = reward_image_to_items(
+        items,
+        image,
+        selection,
+        layout,
+        mode,
+        substitutions,
+        debug_images,
+        timings,
+    )?;
+
+    let Some(items) = items else {
+        return Ok(ScanResult::NoForeground(theme.name.to_string()));
+    };
 
-    if items.is_empty() {
-        return Ok(None);
+    if items.iter().all(Option::is_none) {
+        return Ok(ScanResult::NoMatch);
     }
 
-    let max_len = items.iter().map(|item| item.name.len()).max().unwrap();
-    let highest = items
+    Ok(ScanResult::Found(overlay_from_items(items, scale, theme)))
+}
+
+fn overlay_from_items<'a>(
+    items: Vec<Option<Item>>,
+    scale: f32,
+    theme: Cow<'a, Theme>,
+) -> Overlay<'a> {
+    let max_len = items
         .iter()
-        .max_by_key(|item| item.platinum.unwrap_or_default().floor() as u32)
+        .map(|item| item.as_ref().map_or(UNKNOWN_ITEM, |item| &item.name).len())
+        .max()
         .unwrap();
+    let highest = OverlaySort::default().highest(&items);
 
-    let overlay = Overlay {
-        scale,
-        highest: highest.name.clone(),
-        max_len,
-        items,
-        theme,
-    };
+    Overlay::new(scale, items, max_len, highest, theme)
+}
+
+/// Matches reward names read from the log, none if any of them don't match
+/// so OCR is used instead
+fn find_log_rewards(items: &Items, names: &[String]) -> Option<Vec<Item>> {
+    if names.is_empty() || names.len() > 4 {
+        return None;
+    }
+
+    let rewards = names
+        .iter()
+        .map(|name| items.find_item(name))
+        .collect::<Option<Vec<_>>>();
+
+    if rewards.is_none() {
+        debug!("Rewards in log don't match any items, falling back to OCR: {names:?}");
+    }
+
+    rewards
+}
 
-    Ok(Some(overlay))
+/// Scans `image` with default settings, errors with why if no rewards were found
+pub async fn extract_reward_image<'a>(
+    image: DynamicImage,
+    items: &Items,
+    theme: Option<&'a Theme>,
+) -> Result<Overlay<'a>, BinError> {
+    let layout = RewardLayout::default();
+
+    if !is_reward_screen(&image, layout.scale(&image)?, &layout, &DEFAULT_THEMES) {
+        return Err(BinError::NotRewardScreen);
+    }
+
+    scan(
+        image,
+        items,
+        theme.into(),
+        &layout,
+        OcrMode::default(),
+        &Substitutions::default(),
+        None,
+        &mut Timings::default(),
+    )?
+    .into_result()
 }
 
+/// Scans `image` and shows the overlay if any rewards were found
+///
+/// `image` can come from [take_screenshot] or any external capture, see [read_frame]
+///
+/// returns why nothing was shown if no rewards were found
+///
+/// if `timings` is set, a summary of each stage is printed once the overlay is drawn
 pub async fn activate_overlay(
     image: DynamicImage,
     settings: &ShowOverlaySettings,
-) -> anyhow::Result<()> {
-    let Some(overlay) =
-        extract_reward_image(image, &settings.items, settings.detection_theme.as_ref()).await?
-    else {
-        return Ok(());
+    timings: Option<Timings>,
+) -> Result<ScanResult<()>, BinError> {
+    let print_timings = timings.is_some();
+    let mut timings = timings.unwrap_or_default();
+
+    let selection = match &settings.detection_theme {
+        Some(theme) => ThemeSelection::Fixed(theme),
+        None => ThemeSelection::Auto(&settings.themes),
+    };
+
+    let result = match find_log_rewards(&settings.items, &settings.log_rewards) {
+        Some(items) => {
+            debug!("Using rewards from the log, skipping OCR");
+
+            let scale = settings.reward_layout.scale(&image)?;
+            let theme = selection.select(&image, scale, &settings.reward_layout)?;
+
+            let items = items.into_iter().map(Some).collect();
+            ScanResult::Found(overlay_from_items(items, scale, theme))
+        }
+        None if !settings.force
+            && !is_reward_screen(
+                &image,
+                settings.reward_layout.scale(&image)?,
+                &settings.reward_layout,
+                &settings.themes,
+            ) =>
+        {
+            debug!("Not a reward screen, skipping OCR");
+            ScanResult::NotRewardScreen
+        }
+        None => scan(
+            image,
+            &settings.items,
+            selection,
+            &settings.reward_layout,
+            settings.ocr_mode,
+            &settings.substitutions,
+            settings.debug_images.as_ref(),
+            &mut timings,
+        )?,
+    };
+
+    let mut overlay = match result {
+        ScanResult::Found(overlay) => overlay,
+        result => {
+            if print_timings {
+                eprint!("{timings}");
+            }
+
+            return Ok(result.map(|_| ()));
+        }
+    };
+
+    if settings.market_prices {
+        fetch_market_prices(&mut overlay.items, settings.price_stat).await;
+    }
+
+    let previous_prices = match &settings.price_history {
+        Some(path) => update_price_history(path, &overlay.matched_items()).unwrap_or_else(|err| {
+            warn!("Failed to update price history: {err}");
+            HashMap::new()
+        }),
+        None => HashMap::new(),
     };
 
-    let overlay = Overlay {
-        scale: settings.scale.unwrap_or(overlay.scale),
-        theme: settings.overlay_theme.as_ref().unwrap_or(overlay.theme),
-        ..overlay
+    settings.sort.sort(&mut overlay.items);
+    overlay.highest = settings.sort.highest(&overlay.items);
+    overlay.scale = settings.scale.unwrap_or(overlay.scale);
+    overlay.previous_prices = previous_prices;
+    overlay.timings = print_timings.then_some(timings);
+    overlay.font = settings.font.clone();
+    overlay.fallback_fonts = settings.fallback_fonts.clone();
+    overlay.line_width = settings.line_width;
+    overlay.price_stat = settings.price_stat;
+    overlay.corner_radius = settings.corner_radius;
+    overlay.padding = settings.padding;
+    overlay.background = settings.background;
+    overlay.layout = settings.layout;
+    overlay.precision = settings.precision;
+    overlay.relic = settings.relic.clone();
+
+    if let Some(theme) = &settings.overlay_theme {
+        overlay.theme = Cow::Borrowed(theme);
+    }
+
+    if settings.clipboard
+        && let Err(err) = copy_to_clipboard(&clipboard_summary(&overlay.items))
+    {
+        warn!("Failed to copy rewards to the clipboard: {err}");
+    }
+
+    if settings.output_format != OutputFormat::Overlay {
+        if print_timings && let Some(timings) = &overlay.timings {
+            eprint!("{timings}");
+        }
+
+        print_rewards(&overlay.items, settings.output_format)?;
+        return Ok(ScanResult::Found(()));
+    }
+
+    show_overlay(overlay, settings)?;
+
+    Ok(ScanResult::Found(()))
+}
+
+/// Fills in [Item::market_platinum], warning on failure since wfinfo prices are still shown
+async fn fetch_market_prices(items: &mut [Option<Item>], stat: PriceStat) {
+    let market = match WarframeMarket::new() {
+        Ok(market) => market,
+        Err(err) => {
+            warn!("Failed to create warframe.market client: {err}");
+            return;
+        }
     };
 
-    show_overlay(overlay, settings)
+    for item in items.iter_mut().flatten().filter(|item| !item.ignored) {
+        match market.fetch_price(&item.name, stat).await {
+            Ok(price) => item.market_platinum = price,
+            Err(err) => warn!(
+                "Failed to fetch warframe.market price for {}: {err}",
+                item.name
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -289,11 +946,66 @@ pub struct ShowOverlaySettings {
     pub margin: OverlayMargin,
     pub scale: Option<f32>,
     pub scale_margin: bool,
+    /// Absolute overlay position, only honored on X11
+    pub position: Option<(i32, i32)>,
+    /// Output the overlay is shown on, only honored on wayland
+    pub output: Option<String>,
     pub close_handle: Arc<AtomicBool>,
     pub method: OverlayMethod,
     pub save_path: Option<PathBuf>,
+    /// Crops the saved image to its content, see [OverlayConf::trim]
+    pub trim: bool,
     pub detection_theme: Option<Theme>,
     pub overlay_theme: Option<Theme>,
+    /// Detected from when there's no detection theme
+    pub themes: Themes,
+    /// If set, shows how prices changed since they were last seen
+    pub price_history: Option<PathBuf>,
+    pub ocr_mode: OcrMode,
+    pub substitutions: Substitutions,
+    /// Also shows warframe.market prices next to wfinfo prices
+    pub market_prices: bool,
+    /// Statistic used for warframe.market prices, wfinfo only has an average
+    pub price_stat: PriceStat,
+    /// Main overlay font, common system fonts are used if unset
+    pub font: Option<PathBuf>,
+    /// Fonts used for glyphs the main font doesn't have
+    pub fallback_fonts: Vec<PathBuf>,
+    /// Reward names read from the log, used instead of OCR if they all match items
+    pub log_rewards: Vec<String>,
+    /// Separator width at 1080p, see [Overlay::line_width]
+    pub line_width: f32,
+    /// Background corner radius at 1080p
+    pub corner_radius: f32,
+    /// Padding around the content at 1080p, makes the overlay bigger
+    pub padding: f32,
+    pub background: OverlayBackground,
+    /// Closes the overlay after it has been shown this long
+    pub timeout: Option<Duration>,
+    /// How long the overlay takes to fade in
+    pub fade_in: Option<Duration>,
+    /// How long the overlay takes to fade out after closing
+    pub fade_out: Option<Duration>,
+    /// Whether the overlay takes input, see [OverlayConf::interactive]
+    pub interactive: bool,
+    /// Order rewards are shown in, also decides which reward is highlighted
+    pub sort: OverlaySort,
+    /// Whether rewards are side by side or stacked
+    pub layout: OverlayLayout,
+    /// Whole platinum or one decimal
+    pub precision: PricePrecision,
+    /// Relic the rewards came from, shown above them, only known when activated from the log
+    pub relic: Option<String>,
+    /// Runs OCR even if the image doesn't look like a reward screen, see [is_reward_screen]
+    pub force: bool,
+    /// Where rewards are on screen, see `--ui-scale`
+    pub reward_layout: RewardLayout,
+    /// Prints rewards instead of showing the overlay unless this is [OutputFormat::Overlay]
+    pub output_format: OutputFormat,
+    /// Copies found rewards to the clipboard, see [clipboard_summary]
+    pub clipboard: bool,
+    /// Where the images of each scan step are written, see `--debug-dir`
+    pub debug_images: Option<DebugImages>,
 }
 
 impl Default for ShowOverlaySettings {
@@ -304,16 +1016,45 @@ impl Default for ShowOverlaySettings {
             margin: OverlayMargin::new_top(PIXEL_MARGIN_TOP as i32),
             scale_margin: true,
             scale: None,
+            position: None,
+            output: None,
             close_handle: Arc::new(AtomicBool::new(false)),
             method: OverlayMethod::Auto,
             save_path: None,
+            trim: false,
             detection_theme: None,
             overlay_theme: None,
+            themes: Themes::default(),
+            price_history: None,
+            ocr_mode: OcrMode::default(),
+            substitutions: Substitutions::default(),
+            market_prices: false,
+            price_stat: PriceStat::default(),
+            font: None,
+            fallback_fonts: vec![],
+            log_rewards: vec![],
+            line_width: 1.0,
+            corner_radius: 0.0,
+            padding: 0.0,
+            background: OverlayBackground::default(),
+            timeout: None,
+            fade_in: None,
+            fade_out: None,
+            interactive: true,
+            sort: OverlaySort::RewardOrder,
+            layout: OverlayLayout::Horizontal,
+            precision: PricePrecision::Integer,
+            relic: None,
+            force: false,
+            reward_layout: RewardLayout::default(),
+            output_format: OutputFormat::Overlay,
+            clipboard: false,
+            debug_images: None,
         }
     }
 }
 
-fn show_overlay(overlay: Overlay, settings: &ShowOverlaySettings) -> anyhow::Result<()> {
+fn show_overlay(overlay: Overlay, settings: &ShowOverlaySettings) -> Result<(), BinError> {
     let scale = settings.scale.unwrap_or(overlay.scale);
     let margin = if settings.scale_margin {
         settings.margin.scale(scale)
@@ -321,12 +1062,23 @@ fn show_overlay(overlay: Overlay, settings: &ShowOverlaySettings) -> anyhow::Res
         settings.margin
     };
 
+    let padding = settings.padding * 2.0;
+    let (width, height) = overlay.layout.size(overlay.items.len());
+    let height = height + overlay.header_height();
+
     let conf = OverlayConf {
-        width: ((PIXEL_SINGLE_REWARD_WIDTH * overlay.items.len() as f32) * scale) as u32,
-        height: ((PIXEL_REWARD_HEIGHT / 2.0) * scale) as u32,
+        width: ((width + padding) * scale) as u32,
+        height: ((height + padding) * scale) as u32,
         anchor: settings.anchor,
         margin,
         save_path: settings.save_path.clone(),
+        trim: settings.trim,
+        position: settings.position,
+        output: settings.output.clone(),
+        timeout: settings.timeout,
+        fade_in: settings.fade_in,
+        fade_out: settings.fade_out,
+        interactive: settings.interactive,
         close_handle: settings.close_handle.clone(),
     };
 
@@ -336,9 +1088,44 @@ fn show_overlay(overlay: Overlay, settings: &ShowOverlaySettings) -> anyhow::Res
         settings.method
     };
 
-    let mut backend = get_backend(method).ok_or_else(|| anyhow::anyhow!("Backend not found"))?;
+    let mut backend = get_backend(method).ok_or(BinError::BackendNotFound(method))?;
 
     backend.run(conf, overlay)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_size_overflow_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frame.rgba");
+        std::fs::write(&path, [0; 16]).unwrap();
+
+        assert!(read_frame(&path, Some([u32::MAX, u32::MAX])).is_err());
+        assert!(read_frame(&path, Some([2, 2])).is_ok());
+    }
+
+    #[test]
+    fn crop_keeps_reward_area() {
+        let image = DynamicImage::new_rgb8(1920, 1080);
+        let layout = RewardLayout::default();
+        let [area_x, _, area_w, _] = layout.area(1920, 1080, 1.0);
+        // a 1080p window pushed right until the reward boxes touch the image edge
+        let edge = 1920 - (area_x + area_w).ceil() as u32;
+        let window = |x| Geometry::from([x, 0, 1920, 1080]);
+
+        let cropped = crop_to_geometry(image.clone(), window(edge), 10, &layout).unwrap();
+        assert_eq!(cropped.width(), 1920 - (edge - 10));
+        assert_eq!(cropped.height(), 1080);
+
+        assert!(matches!(
+            crop_to_geometry(image.clone(), window(edge + 1), 10, &layout),
+            Err(BinError::RewardAreaClipped(_))
+        ));
+        assert!(crop_to_geometry(image, window(0), 10, &layout).is_ok());
+    }
+}
@@ -1,17 +1,269 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
 pub use ::overlay::*;
+use lib::market::PriceStat;
 use lib::theme::Theme;
-use lib::util::PIXEL_SINGLE_REWARD_WIDTH;
-use lib::wfinfo::Item;
-use overlay::femtovg::{Canvas, Color, Paint, Renderer};
+use lib::timings::Timings;
+use lib::util::{PIXEL_REWARD_HEIGHT, PIXEL_SINGLE_REWARD_WIDTH};
+use lib::wfinfo::{Item, Price};
+use log::warn;
+use overlay::femtovg::{Canvas, Color, FontId, Paint, Renderer};
 use palette::Hsl;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct Overlay<'a> {
     pub scale: f32,
-    pub items: Vec<Item>,
+    /// None for rewards that didn't match an item, shown as [UNKNOWN_ITEM]
+    pub items: Vec<Option<Item>>,
     pub max_len: usize,
     pub highest: String,
-    pub theme: &'a Theme,
+    pub theme: Cow<'a, Theme>,
+    /// Platinum items had the last time they were seen
+    pub previous_prices: HashMap<String, f32>,
+    /// Printed after the first frame is drawn, see `--timings`
+    pub timings: Option<Timings>,
+    /// Main font, common system fonts are tried if unset or it fails to load, see [load_font]
+    pub font: Option<PathBuf>,
+    /// Fonts glyphs are pulled from when the main font doesn't have them
+    pub fallback_fonts: Vec<PathBuf>,
+    /// Width of separators and the underline at 1080p, never thinner than 1 pixel
+    pub line_width: f32,
+    /// Shown in the warframe.market price label
+    pub price_stat: PriceStat,
+    /// Corner radius of the background at 1080p
+    pub corner_radius: f32,
+    /// Space between the edges and the content at 1080p
+    pub padding: f32,
+    pub background: OverlayBackground,
+    pub layout: OverlayLayout,
+    pub precision: PricePrecision,
+    /// Relic the rewards came from, shown as a header above them, see [PIXEL_HEADER_HEIGHT]
+    pub relic: Option<String>,
+    font_ids: Vec<FontId>,
+}
+
+/// Shown in place of rewards that didn't match an item
+pub const UNKNOWN_ITEM: &str = "Unknown";
+
+/// Shown under rewards that can't be traded, like Forma Blueprint
+pub const NOT_TRADEABLE: &str = "Not tradeable";
+
+/// Height of the relic header at 1080p, added above the rewards when there's a relic
+pub const PIXEL_HEADER_HEIGHT: f32 = 40.0;
+
+/// Color behind the rewards, the area outside rounded corners stays transparent
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OverlayBackground(pub [u8; 4]);
+
+impl Default for OverlayBackground {
+    fn default() -> Self {
+        Self([0, 0, 0, 160])
+    }
+}
+
+impl OverlayBackground {
+    pub fn color(self) -> Color {
+        let [r, g, b, a] = self.0;
+        Color::rgba(r, g, b, a)
+    }
+}
+
+impl std::str::FromStr for OverlayBackground {
+    type Err = anyhow::Error;
+
+    /// `#rrggbb`, `#rrggbbaa` or `r,g,b,a`, the `#` is optional and alpha defaults to 255
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let components = if s.contains(',') {
+            s.split(',')
+                .map(|c| c.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            let hex = s.strip_prefix('#').unwrap_or(s);
+
+            if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+                return Err(anyhow::anyhow!("Invalid hex color {s:?}"));
+            }
+
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        match components[..] {
+            [r, g, b] => Ok(Self([r, g, b, 255])),
+            [r, g, b, a] => Ok(Self([r, g, b, a])),
+            _ => Err(anyhow::anyhow!(
+                "Invalid color {s:?}, expected #rrggbb, #rrggbbaa or r,g,b,a"
+            )),
+        }
+    }
+}
+
+/// Order rewards are drawn in, the highlighted reward is the best by the same metric
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OverlaySort {
+    /// Same order as the reward screen, highlights the most platinum
+    #[default]
+    RewardOrder,
+    Platinum,
+    Ducats,
+    /// Ducats per platinum
+    DucatEfficiency,
+}
+
+impl OverlaySort {
+    /// Value `item` is sorted by, higher is better
+    pub fn metric(self, item: &Item) -> f32 {
+        let platinum = item.platinum.map(|p| p.value());
+        let ducats = item.ducats.map(|d| d as f32);
+
+        match self {
+            Self::RewardOrder | Self::Platinum => platinum.unwrap_or_default(),
+            Self::Ducats => ducats.unwrap_or_default(),
+            Self::DucatEfficiency => match (ducats, platinum) {
+                (Some(ducats), Some(platinum)) if platinum > 0.0 => ducats / platinum,
+                _ => 0.0,
+            },
+        }
+    }
+
+    /// Best first, unknown rewards go last, does nothing for [OverlaySort::RewardOrder]
+    pub fn sort(self, items: &mut [Option<Item>]) {
+        if self == Self::RewardOrder {
+            return;
+        }
+
+        let metric = |item: &Option<Item>| item.as_ref().map(|item| self.metric(item));
+
+        items.sort_by(|a, b| match (metric(a), metric(b)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+    }
+
+    /// Name of the best item, empty if none matched
+    ///
+    /// ties go to the item with more platinum, compared before rounding for display
+    pub fn highest(self, items: &[Option<Item>]) -> String {
+        let platinum = |item: &Item| item.platinum.map(|p| p.value()).unwrap_or_default();
+
+        items
+            .iter()
+            .flatten()
+            .max_by(|a, b| {
+                self.metric(a)
+                    .total_cmp(&self.metric(b))
+                    .then_with(|| platinum(a).total_cmp(&platinum(b)))
+            })
+            .map(|item| item.name.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// How prices are shown in the overlay, sorting always uses the exact price
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PricePrecision {
+    /// Rounded down to whole platinum, like 19
+    #[default]
+    Integer,
+    /// One decimal, like 19.9
+    Decimal,
+}
+
+impl PricePrecision {
+    pub fn format(self, price: Price) -> String {
+        match self {
+            Self::Integer => format!("{price}"),
+            Self::Decimal => format!("{price:.1}"),
+        }
+    }
+}
+
+/// Direction rewards are laid out in
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OverlayLayout {
+    /// Side by side in a wide strip, like the reward screen
+    #[default]
+    Horizontal,
+    /// Stacked on top of each other, reads better on portrait monitors or anchored to a side
+    Vertical,
+}
+
+impl OverlayLayout {
+    /// Size of the content for `count` rewards at 1080p, without padding
+    pub fn size(self, count: usize) -> (f32, f32) {
+        let count = count as f32;
+
+        match self {
+            Self::Horizontal => (PIXEL_SINGLE_REWARD_WIDTH * count, PIXEL_REWARD_HEIGHT / 2.0),
+            Self::Vertical => (PIXEL_SINGLE_REWARD_WIDTH, PIXEL_REWARD_HEIGHT / 2.0 * count),
+        }
+    }
+
+    /// Top left corner of the reward at `index` at 1080p
+    pub fn origin(self, index: usize) -> (f32, f32) {
+        let index = index as f32;
+
+        match self {
+            Self::Horizontal => (PIXEL_SINGLE_REWARD_WIDTH * index, 0.0),
+            Self::Vertical => (0.0, PIXEL_REWARD_HEIGHT / 2.0 * index),
+        }
+    }
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(
+        scale: f32,
+        items: Vec<Option<Item>>,
+        max_len: usize,
+        highest: String,
+        theme: Cow<'a, Theme>,
+    ) -> Self {
+        Self {
+            scale,
+            items,
+            max_len,
+            highest,
+            theme,
+            previous_prices: HashMap::new(),
+            timings: None,
+            font: None,
+            fallback_fonts: vec![],
+            line_width: 1.0,
+            price_stat: PriceStat::default(),
+            corner_radius: 0.0,
+            padding: 0.0,
+            background: OverlayBackground::default(),
+            layout: OverlayLayout::default(),
+            precision: PricePrecision::default(),
+            relic: None,
+            font_ids: vec![],
+        }
+    }
+
+    /// Height of the relic header at 1080p, 0 without a relic
+    pub fn header_height(&self) -> f32 {
+        if self.relic.is_some() {
+            PIXEL_HEADER_HEIGHT
+        } else {
+            0.0
+        }
+    }
+
+    /// Items that matched, skipping unknown rewards
+    pub fn matched_items(&self) -> Vec<Item> {
+        self.items.iter().flatten().cloned().collect()
+    }
 }
 
 pub fn color_from_hsl(hsl: Hsl) -> Color {
@@ -26,58 +278,249 @@ pub fn color_from_hsl(hsl: Hsl) -> Color {
     Color::hsl(hue, saturation, lightness)
 }
 
+/// Rect snapped to whole pixels, an edge between pixels gets anti-aliased
+/// into two faint rows, which is how thin lines disappear at some scales
+fn pixel_rect(x: f32, y: f32, width: f32, height: f32) -> femtovg::Path {
+    let mut path = femtovg::Path::new();
+    path.rect(
+        x.round(),
+        y.round(),
+        width.round().max(1.0),
+        height.round().max(1.0),
+    );
+    path
+}
+
+/// `line_width` at `scale` in whole pixels and at least 1,
+/// so lines don't vanish at fractional scales or get blurry
+fn scaled_line_width(line_width: f32, scale: f32) -> f32 {
+    (line_width * scale).round().max(1.0)
+}
+
+fn with_opacity(mut color: Color, opacity: f32) -> Color {
+    color.a *= opacity;
+    color
+}
+
 impl<T: Renderer> OverlayRenderer<T> for Overlay<'_> {
     fn setup(&mut self, canvas: &mut Canvas<T>, _: &OverlayTime) -> Result<(), Error> {
-        canvas.add_font("/usr/share/fonts/TTF/DejaVuSans.ttf")?;
+        self.font_ids = vec![load_font(canvas, self.font.as_deref())?];
+
+        for font in &self.fallback_fonts {
+            match canvas.add_font(font) {
+                Ok(id) => self.font_ids.push(id),
+                Err(err) => warn!("Failed to load fallback font {}: {err:?}", font.display()),
+            }
+        }
+
+        let paint = Paint::default()
+            .with_font(&self.font_ids)
+            .with_font_size(16.0);
+
+        for item in self.items.iter().flatten() {
+            let width = canvas.measure_text(0.0, 0.0, &item.name, &paint)?.width();
+
+            if !item.name.is_empty() && width == 0.0 {
+                warn!(
+                    "No loaded font can render {:?}, try installing a broader font or adding a fallback font",
+                    item.name
+                );
+            }
+        }
+
         Ok(())
     }
 
-    fn draw(&mut self, canvas: &mut Canvas<T>, _: &OverlayTime) -> Result<(), Error> {
+    fn draw(&mut self, canvas: &mut Canvas<T>, time: &OverlayTime) -> Result<(), Error> {
+        let start = Instant::now();
         let pixel_single_reward_width = PIXEL_SINGLE_REWARD_WIDTH * self.scale;
         let fs = PIXEL_SINGLE_REWARD_WIDTH / (self.max_len as f32 / 1.75);
+        let line_width = scaled_line_width(self.line_width, self.scale);
 
-        let primary = Paint::color(color_from_hsl(self.theme.primary))
-            .with_line_width(1.0 * self.scale)
+        let primary_color = with_opacity(color_from_hsl(self.theme.primary), time.opacity);
+        let secondary_color = with_opacity(color_from_hsl(self.theme.secondary), time.opacity);
+
+        let primary = Paint::color(primary_color)
+            .with_font(&self.font_ids)
+            .with_line_width(line_width)
             .with_font_size(fs * self.scale);
 
         let secondary = primary
             .clone() //
-            .with_color(color_from_hsl(self.theme.secondary));
+            .with_color(secondary_color);
 
         let fs = primary.font_size();
 
-        canvas.clear_rect(
-            0,
-            0,
-            canvas.width(),
-            canvas.height(),
-            Color::rgba(0, 0, 0, 160),
-        );
+        let background = with_opacity(self.background.color(), time.opacity);
+        let corner_radius = self.corner_radius * self.scale;
+        let padding = self.padding * self.scale;
+        // size of the content inside the padding
+        let header_height = self.header_height() * self.scale;
+        let width = canvas.width() as f32 - padding * 2.0;
+        let height = canvas.height() as f32 - padding * 2.0 - header_height;
+
+        if corner_radius > 0.0 {
+            let (canvas_width, canvas_height) = (canvas.width(), canvas.height());
+            canvas.clear_rect(0, 0, canvas_width, canvas_height, Color::rgba(0, 0, 0, 0));
+
+            let mut panel = femtovg::Path::new();
+            panel.rounded_rect(
+                0.0,
+                0.0,
+                canvas_width as _,
+                canvas_height as _,
+                corner_radius,
+            );
+            canvas.fill_path(&panel, &Paint::color(background));
+        } else {
+            canvas.clear_rect(0, 0, canvas.width(), canvas.height(), background);
+        }
 
-        let mut line = femtovg::Path::new();
-        line.rect(0.0, fs * 1.2, canvas.width() as _, 1. * self.scale);
-        canvas.fill_path(&line, &secondary);
+        canvas.save();
+        // whole pixels so lines drawn with pixel_rect stay on the pixel grid
+        canvas.translate(padding.round(), padding.round());
+
+        if let Some(relic) = &self.relic {
+            let header = secondary.clone().with_font_size(header_height * 0.6);
+            let size = canvas.measure_text(0.0, 0.0, relic, &header)?;
+            let x = (width - size.width()) / 2.0;
+            let y = (header_height + size.height()) / 2.0;
+            canvas.fill_text(x, y, relic, &header)?;
+
+            let line = pixel_rect(0.0, header_height - line_width, width, line_width);
+            canvas.fill_path(&line, &secondary);
+
+            canvas.translate(0.0, header_height.round());
+        }
 
         // let offset_factor = 1.1666666666666667;
         let offset_factor = 1.2;
+        // keeps rows aligned when only some items have a market price
+        let market_rows = if self
+            .items
+            .iter()
+            .flatten()
+            .any(|item| item.market_platinum.is_some())
+        {
+            1.0
+        } else {
+            0.0
+        };
+        let set_rows = if self
+            .items
+            .iter()
+            .flatten()
+            .any(|item| item.set_platinum.is_some())
+        {
+            1.0
+        } else {
+            0.0
+        };
+        // rows after platinum, market and set prices
+        let extra_rows = market_rows + set_rows;
         for (i, item) in self.items.iter().enumerate() {
-            let i = i as f32;
-            let x = pixel_single_reward_width * i;
+            let (x, top) = self.layout.origin(i);
+            let (x, top) = (x * self.scale, top * self.scale);
+            let name_y = top + fs;
 
-            let offset = canvas.measure_text(x, fs, &item.name, &primary)?;
+            // separates the name from the rows below it
+            let line = pixel_rect(x, top + fs * 1.2, pixel_single_reward_width, line_width);
+            canvas.fill_path(&line, &secondary);
+
+            if i > 0 {
+                let line = match self.layout {
+                    OverlayLayout::Horizontal => pixel_rect(x, 0.0, line_width, height),
+                    OverlayLayout::Vertical => pixel_rect(0.0, top, width, line_width),
+                };
+
+                canvas.fill_path(&line, &secondary);
+            }
+
+            let Some(item) = item else {
+                let offset = canvas.measure_text(x, name_y, UNKNOWN_ITEM, &primary)?;
+                let offset = (pixel_single_reward_width - offset.width()) / 2.0;
+                canvas.fill_text(x + offset, name_y, UNKNOWN_ITEM, &primary)?;
+                continue;
+            };
+
+            let name = canvas.fit_text(&item.name, &primary, pixel_single_reward_width)?;
+            let offset = canvas.measure_text(x, name_y, &name, &primary)?;
             let offset = (pixel_single_reward_width - offset.width()) / 2.0;
 
             if self.highest == item.name {
-                canvas.fill_text(x + offset, fs, &item.name, &secondary)?;
+                canvas.fill_text(x + offset, name_y, &name, &secondary)?;
             } else {
-                canvas.fill_text(x + offset, fs, &item.name, &primary)?;
+                canvas.fill_text(x + offset, name_y, &name, &primary)?;
+            }
+
+            if !item.is_tradeable() {
+                let y = top + fs * (offset_factor * 2.0);
+                let offset = canvas.measure_text(x, y, NOT_TRADEABLE, &primary)?;
+                let offset = (pixel_single_reward_width - offset.width()) / 2.0;
+                canvas.fill_text(x + offset, y, NOT_TRADEABLE, &primary)?;
+                continue;
             }
 
             if let Some(platinum) = item.platinum {
-                let y = fs * (offset_factor * 2.0);
+                let y = top + fs * (offset_factor * 2.0);
                 let text = "Platinum: ";
-                let value = platinum.floor() as u32;
-                let value = format!("{value}");
+                let value = self.precision.format(platinum);
+                let delta = self
+                    .previous_prices
+                    .get(&item.name)
+                    .map(|previous| (platinum.value() - previous).round() as i32)
+                    .map(|delta| match delta {
+                        1.. => format!(" ▲{delta}"),
+                        ..0 => format!(" ▼{}", delta.abs()),
+                        0 => String::new(),
+                    })
+                    .unwrap_or_default();
+
+                let offset =
+                    canvas.measure_text(y, fs, format!("{text}{value}{delta}"), &secondary)?;
+
+                let offset = (pixel_single_reward_width - offset.width()) / 2.0;
+                let avg = canvas.draw_text(offset + x, y, text, &primary, None)?;
+
+                let value = canvas.draw_text(
+                    offset + avg.width() + x,
+                    y, //
+                    &value,
+                    &secondary,
+                    None,
+                )?;
+
+                canvas.draw_text(
+                    offset + avg.width() + value.width() + x,
+                    y,
+                    &delta,
+                    &primary,
+                    None,
+                )?;
+            }
+
+            if let Some(market_platinum) = item.market_platinum {
+                let y = top + fs * (offset_factor * 3.0);
+                let text = format!("Market ({}): ", self.price_stat);
+                let value = self.precision.format(market_platinum);
+                let offset = canvas.measure_text(y, fs, format!("{text}{value}"), &secondary)?;
+
+                let offset = (pixel_single_reward_width - offset.width()) / 2.0;
+                let avg = canvas.draw_text(offset + x, y, text, &primary, None)?;
+
+                canvas.draw_text(
+                    offset + avg.width() + x,
+                    y, //
+                    &value,
+                    &secondary,
+                    None,
+                )?;
+            }
+
+            if let Some(set_platinum) = item.set_platinum {
+                let y = top + fs * (offset_factor * (3.0 + market_rows));
+                let text = "Set: ";
+                let value = self.precision.format(set_platinum);
                 let offset = canvas.measure_text(y, fs, format!("{text}{value}"), &secondary)?;
 
                 let offset = (pixel_single_reward_width - offset.width()) / 2.0;
@@ -93,7 +536,7 @@ impl<T: Renderer> OverlayRenderer<T> for Overlay<'_> {
             }
 
             if let Some(ducats) = item.ducats {
-                let y = fs * (offset_factor * 3.0);
+                let y = top + fs * (offset_factor * (3.0 + extra_rows));
                 let text = "Ducats: ";
                 let offset = canvas.measure_text(y, fs, format!("{text}{}", ducats), &secondary)?;
 
@@ -110,9 +553,9 @@ impl<T: Renderer> OverlayRenderer<T> for Overlay<'_> {
             }
 
             if let (Some(platinum), Some(ducats)) = (item.platinum, item.ducats) {
-                let y = fs * (offset_factor * 4.0);
+                let y = top + fs * (offset_factor * (4.0 + extra_rows));
                 let text = "Ducats/Platinum: ";
-                let value = ducats as f32 / platinum;
+                let value = ducats as f32 / platinum.value();
                 let value = format!("{:.2}", value);
                 let offset = canvas.measure_text(y, fs, format!("{text}{value}"), &secondary)?;
 
@@ -128,7 +571,7 @@ impl<T: Renderer> OverlayRenderer<T> for Overlay<'_> {
                 )?;
             }
 
-            let y = fs * (offset_factor * 5.0);
+            let y = top + fs * (offset_factor * (5.0 + extra_rows));
             let text = "Vaulted: ";
             let value = format!("{}", item.vaulted);
             let offset = canvas.measure_text(y, fs, format!("{text}{value}"), &secondary)?;
@@ -143,21 +586,43 @@ impl<T: Renderer> OverlayRenderer<T> for Overlay<'_> {
                 &secondary,
                 None,
             )?;
+        }
 
-            if i as usize == self.items.len() - 1 {
-                continue;
-            }
+        canvas.restore();
 
-            let mut line = femtovg::Path::new();
-            line.rect(
-                pixel_single_reward_width + (pixel_single_reward_width * i),
-                0.0,
-                1. * self.scale,
-                canvas.height() as _,
-            );
-            canvas.fill_path(&line, &secondary);
+        if let Some(mut timings) = self.timings.take() {
+            timings.record("overlay render", start);
+            eprint!("{timings}");
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_range_format() {
+        let range = Price::Range {
+            lo: 40.6,
+            hi: 55.25,
+        };
+
+        assert_eq!(PricePrecision::Integer.format(range), "40–55");
+        assert_eq!(PricePrecision::Decimal.format(range), "40.6–55.2");
+        assert_eq!(PricePrecision::Integer.format(Price::Avg(19.9)), "19");
+        assert_eq!(PricePrecision::Decimal.format(Price::Avg(19.9)), "19.9");
+    }
+
+    #[test]
+    fn line_width_is_at_least_a_pixel() {
+        for scale in [0.25, 0.33, 0.5, 0.75] {
+            assert_eq!(scaled_line_width(1.0, scale), 1.0, "{scale}");
+        }
+
+        assert_eq!(scaled_line_width(1.0, 1.5), 2.0);
+        assert_eq!(scaled_line_width(3.0, 0.33), 1.0);
+    }
+}
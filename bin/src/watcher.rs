@@ -1,45 +1,303 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use log::debug;
+use lib::wfinfo::Refinement;
+use log::{debug, warn};
 use notify::{Config, EventKind, RecursiveMode, Watcher};
 
-pub fn get_default_ee_log_path() -> PathBuf {
-    dirs::data_local_dir()
-        .unwrap()
-        .join("Steam/steamapps/compatdata/230410/pfx/drive_c")
-        .join("users/steamuser/AppData/Local/Warframe")
+const WARFRAME_APP_ID: &str = "230410";
+
+#[derive(Debug, Clone, Copy)]
+pub struct WatcherSettings {
+    /// How often logs are polled on platforms without file events
+    pub poll_interval: Duration,
+    /// Reward screens within this long of the last activation are ignored,
+    /// so one reward screen logged over multiple writes activates once
+    pub debounce: Duration,
+}
+
+impl Default for WatcherSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(100),
+            debounce: Duration::from_millis(1500),
+        }
+    }
+}
+
+/// EE.log inside the proton prefix of a steam library
+fn proton_ee_log_path(library: &Path) -> PathBuf {
+    library
+        .join("steamapps/compatdata")
+        .join(WARFRAME_APP_ID)
+        .join("pfx/drive_c/users/steamuser/AppData/Local/Warframe")
         .join("EE.log")
 }
 
+/// Native, flatpak and legacy steam installs
+fn steam_roots() -> Vec<PathBuf> {
+    let mut roots = vec![];
+
+    if let Some(data) = dirs::data_local_dir() {
+        roots.push(data.join("Steam"));
+    }
+
+    if let Some(home) = dirs::home_dir() {
+        roots.push(home.join(".steam/steam"));
+        roots.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"));
+    }
+
+    roots
+}
+
+/// Library paths listed in `libraryfolders.vdf`, which looks like
+///
+/// ```text
+/// "libraryfolders"
+/// {
+///     "0"
+///     {
+///         "path"      "/home/user/.local/share/Steam"
+///         ...
+/// ```
+pub fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('"').skip(1).step_by(2);
+
+            match (fields.next(), fields.next()) {
+                (Some("path"), Some(path)) => Some(PathBuf::from(path.replace("\\\\", "\\"))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Every place EE.log could be, in the order they are checked
+pub fn ee_log_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![];
+
+    if cfg!(windows)
+        && let Some(local) = dirs::data_local_dir()
+    {
+        candidates.push(local.join("Warframe/EE.log"));
+    }
+
+    for root in steam_roots() {
+        candidates.push(proton_ee_log_path(&root));
+
+        let vdf = root.join("steamapps/libraryfolders.vdf");
+        let Ok(vdf) = std::fs::read_to_string(vdf) else {
+            continue;
+        };
+
+        for library in parse_library_folders(&vdf) {
+            let path = proton_ee_log_path(&library);
+
+            if !candidates.contains(&path) {
+                candidates.push(path);
+            }
+        }
+    }
+
+    candidates
+}
+
+/// First EE.log that exists, see [ee_log_candidates],
+/// falls back to the first candidate, which is steam's default proton prefix on linux
+pub fn get_default_ee_log_path() -> PathBuf {
+    let candidates = ee_log_candidates();
+
+    if let Some(path) = candidates.iter().find(|path| path.exists()) {
+        debug!("Found EE.log at {}", path.display());
+        return path.clone();
+    }
+
+    candidates
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| PathBuf::from("EE.log"))
+}
+
+/// `activate` gets any reward names found in the log, see [parse_reward_name],
+/// and the last relic seen in the log, see [parse_relic]
 pub fn log_watcher(
     file: impl AsRef<Path>,
-    activate: impl Fn(),
+    activate: impl Fn(Vec<String>, Option<String>),
     deactivate: impl Fn(),
 ) -> anyhow::Result<()> {
-    debug!("Watching {}", file.as_ref().display());
+    log_watcher_many(
+        &[file.as_ref().to_path_buf()],
+        WatcherSettings::default(),
+        activate,
+        deactivate,
+    )
+}
 
-    let (tx, rx) = std::sync::mpsc::channel();
-    let config = Config::default().with_poll_interval(Duration::from_millis(100));
+/// Turns an item path logged around the reward screen into a readable name,
+/// `/Lotus/StoreItems/Types/Recipes/WarframeRecipes/AshPrimeHelmetBlueprint`
+/// becomes `Ash Prime Helmet Blueprint`
+///
+/// these are internal names, so they don't always match wfinfo names
+pub fn parse_reward_name(line: &str) -> Option<String> {
+    let path = line
+        .split_ascii_whitespace()
+        .find(|word| word.starts_with("/Lotus/StoreItems/"))?;
 
-    let mut pos = File::open(file.as_ref())?.seek(SeekFrom::End(0))?;
+    let id = path.rsplit('/').next()?;
+    let id = id.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
 
-    let mut watcher = notify::RecommendedWatcher::new(tx, config)?;
-    watcher.watch(file.as_ref(), RecursiveMode::NonRecursive)?;
+    if id.is_empty() {
+        return None;
+    }
 
-    loop {
-        let event = rx.recv()??;
+    let mut name = String::new();
+    for c in id.chars() {
+        if c.is_ascii_uppercase() && !name.is_empty() {
+            name.push(' ');
+        }
 
-        let EventKind::Modify(_) = event.kind else {
-            continue;
-        };
+        name.push(c);
+    }
+
+    Some(name)
+}
+
+/// Turns a relic path logged when a relic is equipped into its tier and refinement,
+/// `/Lotus/Types/Game/Projections/T4VoidProjectionVaultABronze` becomes `Axi Intact`
+///
+/// the relic's letter and number are internal ids in the log, so they're left out
+pub fn parse_relic(line: &str) -> Option<String> {
+    let path = line
+        .split_ascii_whitespace()
+        .find(|word| word.starts_with("/Lotus/Types/Game/Projections/"))?;
+
+    let id = path.rsplit('/').next()?;
+    let id = id.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+    let (tier, rest) = id.strip_prefix('T')?.split_at_checked(1)?;
+    let rest = rest.strip_prefix("VoidProjection")?;
+
+    let tier = match tier {
+        "1" => "Lith",
+        "2" => "Meso",
+        "3" => "Neo",
+        "4" => "Axi",
+        "5" => "Requiem",
+        _ => return None,
+    };
+
+    let refinement = [
+        ("Bronze", Refinement::Intact),
+        ("Silver", Refinement::Exceptional),
+        ("Gold", Refinement::Flawless),
+        ("Platinum", Refinement::Radiant),
+    ]
+    .into_iter()
+    .find(|(suffix, _)| rest.ends_with(suffix))
+    .map(|(_, refinement)| refinement)?;
 
-        let mut file = File::open(file.as_ref())?;
-        file.seek(SeekFrom::Start(pos))?;
-        let reader = BufReader::new(&mut file);
-        let mut lines = reader.lines().map_while(Result::ok);
+    Some(format!("{tier} {refinement:?}"))
+}
+
+/// Reads lines appended to `file` since `pos`, moving `pos` to the end
+///
+/// if the file shrank it was truncated or rotated, so it's read from the start again
+fn read_new_lines(path: &Path, pos: &mut u64) -> anyhow::Result<Vec<String>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        // deleted, it's read from the start once it's created again
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            *pos = 0;
+            return Ok(vec![]);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let len = file.metadata()?.len();
+
+    if len < *pos {
+        warn!(
+            "{} was truncated or rotated, reading from the start",
+            path.display()
+        );
+        *pos = 0;
+    }
+
+    if len == *pos {
+        return Ok(vec![]);
+    }
+
+    file.seek(SeekFrom::Start(*pos))?;
+    let reader = BufReader::new(&mut file);
+    let lines = reader.lines().map_while(Result::ok).collect();
+
+    *pos = len;
+
+    Ok(lines)
+}
+
+/// Logs being watched and what was last read from them, events are passed in
+/// so reading is separate from how file changes are noticed
+struct LogReader {
+    files: Vec<PathBuf>,
+    positions: Vec<u64>,
+    // relics are equipped well before the reward screen, so the last one is kept per log
+    relics: Vec<Option<String>>,
+    debounce: Duration,
+    /// When the last activation finished, rewards logged while it ran are ignored too
+    last_activation: Option<Instant>,
+}
+
+impl LogReader {
+    /// Starts reading each log from its current end
+    fn new(files: &[PathBuf], debounce: Duration) -> anyhow::Result<Self> {
+        // event paths are the watched directory joined with the file name
+        let files = files
+            .iter()
+            .map(|file| file.canonicalize())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let positions = files
+            .iter()
+            .map(|file| File::open(file)?.seek(SeekFrom::End(0)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            relics: vec![None; files.len()],
+            files,
+            positions,
+            debounce,
+            last_activation: None,
+        })
+    }
+
+    /// Reads lines appended to the log at `index` and runs the callbacks for them,
+    /// `recreated` reads it from the start
+    fn read(
+        &mut self,
+        index: usize,
+        recreated: bool,
+        activate: &impl Fn(Vec<String>, Option<String>),
+        deactivate: &impl Fn(),
+    ) -> anyhow::Result<()> {
+        let file = &self.files[index];
+        let pos = &mut self.positions[index];
+        let relic = &mut self.relics[index];
+
+        if recreated {
+            debug!("{} was recreated, reading from the start", file.display());
+            *pos = 0;
+        }
+
+        let new_lines = read_new_lines(file, pos)?;
+
+        if let Some(new_relic) = new_lines.iter().rev().find_map(|line| parse_relic(line)) {
+            debug!("Relic in log: {new_relic}");
+            *relic = Some(new_relic);
+        }
+
+        let mut lines = new_lines.into_iter();
 
         let got_rewards = lines.any(|line| {
             line.contains("Pause countdown done")
@@ -47,11 +305,26 @@ pub fn log_watcher(
                 || line.contains("Created /Lotus/Interface/ProjectionRewardChoice.swf")
         });
 
-        if got_rewards {
-            debug!("Watcher pos = {pos:?}");
-            debug!("Activating");
+        let debounced = self
+            .last_activation
+            .is_some_and(|last| last.elapsed() < self.debounce);
+
+        if got_rewards && debounced {
+            debug!("Ignoring rewards logged right after the last activation");
+        } else if got_rewards {
+            debug!("Watcher file = {}, pos = {pos:?}", file.display());
+
+            let rewards = lines
+                .clone()
+                .filter_map(|line| parse_reward_name(&line))
+                .collect::<Vec<_>>();
 
-            activate();
+            debug!("Activating, rewards in log: {rewards:?}, relic: {relic:?}");
+
+            activate(rewards, relic.clone());
+
+            // activating can wait for the reward screen, lines logged meanwhile are read next
+            self.last_activation = Some(Instant::now());
         }
 
         let reward_selected = lines.any(|line| {
@@ -61,11 +334,119 @@ pub fn log_watcher(
         });
 
         if reward_selected {
-            debug!("Watcher pos = {pos:?}");
+            debug!("Watcher file = {}, pos = {pos:?}", file.display());
             debug!("Deactivating");
             deactivate();
         }
 
-        pos = file.metadata()?.len();
+        Ok(())
+    }
+}
+
+/// Same as [log_watcher] but for multiple logs, like multiple accounts or steam libraries,
+/// callbacks fire regardless of which log triggered them
+pub fn log_watcher_many(
+    files: &[PathBuf],
+    settings: WatcherSettings,
+    activate: impl Fn(Vec<String>, Option<String>),
+    deactivate: impl Fn(),
+) -> anyhow::Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let config = Config::default().with_poll_interval(settings.poll_interval);
+
+    let mut watcher = notify::RecommendedWatcher::new(tx, config)?;
+    let mut reader = LogReader::new(files, settings.debounce)?;
+
+    for file in &reader.files {
+        debug!("Watching {}", file.display());
+
+        // the directory is watched so the watch survives the log being deleted and recreated
+        let dir = file.parent().unwrap_or(file);
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    loop {
+        let event = rx.recv()??;
+
+        let recreated = match event.kind {
+            EventKind::Create(_) => true,
+            EventKind::Modify(_) => false,
+            _ => continue,
+        };
+
+        for index in 0..reader.files.len() {
+            if event.paths.contains(&reader.files[index]) {
+                reader.read(index, recreated, &activate, &deactivate)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io::Write;
+
+    use super::*;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn rewards_within_debounce_activate_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("EE.log");
+        std::fs::write(&log, "").unwrap();
+
+        let debounce = Duration::from_millis(200);
+        let mut reader = LogReader::new(std::slice::from_ref(&log), debounce).unwrap();
+        let activations = Cell::new(0);
+        // like the cli, activating waits longer than the debounce for the reward screen
+        let activate = |_, _| {
+            activations.set(activations.get() + 1);
+            std::thread::sleep(debounce * 2);
+        };
+
+        append(&log, "Got rewards\n");
+        reader.read(0, false, &activate, &|| {}).unwrap();
+        append(&log, "Pause countdown done\n");
+        reader.read(0, false, &activate, &|| {}).unwrap();
+
+        assert_eq!(activations.get(), 1);
+
+        std::thread::sleep(debounce);
+        append(&log, "Got rewards\n");
+        reader.read(0, false, &activate, &|| {}).unwrap();
+
+        assert_eq!(activations.get(), 2);
+    }
+
+    #[test]
+    fn truncated_and_recreated_logs_still_activate() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("EE.log");
+        std::fs::write(&log, "").unwrap();
+
+        let mut reader = LogReader::new(std::slice::from_ref(&log), Duration::ZERO).unwrap();
+        let activations = Cell::new(0);
+        let activate = |_, _| activations.set(activations.get() + 1);
+
+        append(&log, &"Sys [Info]: loading\n".repeat(20));
+        reader.read(0, false, &activate, &|| {}).unwrap();
+        assert_eq!(activations.get(), 0);
+
+        // shorter than what was already read
+        std::fs::write(&log, "Got rewards\n").unwrap();
+        reader.read(0, false, &activate, &|| {}).unwrap();
+        assert_eq!(activations.get(), 1);
+
+        std::fs::remove_file(&log).unwrap();
+        reader.read(0, false, &activate, &|| {}).unwrap();
+
+        std::fs::write(&log, "Got rewards\n").unwrap();
+        reader.read(0, true, &activate, &|| {}).unwrap();
+        assert_eq!(activations.get(), 2);
     }
 }
@@ -1,97 +1,585 @@
+use std::fs::File;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::process::ExitCode;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use bin::args::{ArgDetectionMethod, ArgShortcutMethod, Args, get_default_presets_path};
+use bin::cache::{
+    FetchMode, get_default_price_history_path, get_default_theme_cache_path, get_items,
+    get_relics, load_theme_name, save_theme_name,
+};
+use bin::geometry::GeometryMethod;
+use bin::watcher::{WatcherSettings, get_default_ee_log_path, log_watcher_many};
+use bin::{
+    BinError, ScanResult, ShortcutBinding, ShortcutSettings, ShowOverlaySettings, crop_to_geometry, read_frame, take_screenshot,
+};
+use image::DynamicImage;
+use lib::hdr::tonemap;
+use lib::ocr::{Substitutions, reward_image_to_reward_names};
+use lib::theme::{Theme, ThemeSelection, auto_theme};
+use lib::timings::Timings;
+use lib::wfinfo::{Items, PriceFormat, load_from_reader};
+use log::{debug, error, warn};
+
+/// Theme rewards are filtered with, None to detect it from `args.misc.themes`
+fn detection_theme(
+    image: &DynamicImage,
+    overlay_theme: Option<Theme>,
+    args: &Args,
+) -> anyhow::Result<Option<Theme>> {
+    let theme = match &args.misc.detection_method {
+        ArgDetectionMethod::Auto => Some(auto_theme("auto", image)?),
+        ArgDetectionMethod::Overlay => overlay_theme,
+        ArgDetectionMethod::Default(theme) => Some(theme.deref().clone()),
+        ArgDetectionMethod::Custom(theme) => Some(theme.clone()),
+    };
 
-use bin::args::{ArgDetectionMethod, ArgShortcutMethod, Args};
-use bin::cache::get_items;
-use bin::watcher::{get_default_ee_log_path, log_watcher};
-use bin::{ShortcutSettings, ShowOverlaySettings, take_screenshot};
-use lib::theme::{DEFAULT_THEMES, auto_theme};
-use lib::util::get_scale;
-use lib::wfinfo::Items;
-use log::{debug, error};
+    Ok(theme)
+}
 
-async fn activate(
+fn overlay_settings(
     items: Arc<Items>,
     close_handle: Arc<AtomicBool>,
-    active_handle: Arc<AtomicBool>,
+    image: &DynamicImage,
+    overlay_theme: Option<Theme>,
+    log_rewards: &[String],
+    relic: Option<String>,
     args: &Args,
-) -> anyhow::Result<()> {
-    let geometry_method = args.geometry.method.clone();
-
-    let image = match &args.image {
-        None => take_screenshot(geometry_method).await?,
-        Some(image) => image::open(image)?,
-    };
-
-    let scale = get_scale(&image)?;
-
-    let overlay_theme = args
-        .overlay
-        .theme
-        .map(|t| t.into())
-        .or_else(|| DEFAULT_THEMES.detect_theme(&image, scale))
-        .cloned();
+) -> anyhow::Result<ShowOverlaySettings> {
+    let detection_theme = detection_theme(image, overlay_theme.clone(), args)?;
 
-    let detection_theme = match &args.misc.detection_method {
-        ArgDetectionMethod::Auto => Some(auto_theme("auto", &image)?),
-        ArgDetectionMethod::Overlay => overlay_theme.clone(),
-        ArgDetectionMethod::Default(theme) => Some(theme.deref().clone()),
-        ArgDetectionMethod::Custom(theme) => Some(theme.clone()),
+    let substitutions = match &args.misc.substitutions {
+        Some(path) => Substitutions::with_defaults(load_from_reader(File::open(path)?)?),
+        None => Substitutions::default(),
     };
 
-    let settings = ShowOverlaySettings {
+    Ok(ShowOverlaySettings {
         items,
         anchor: args.overlay.anchor,
         margin: args.overlay.margin,
         scale: args.overlay.scale,
         scale_margin: args.overlay.scale_margin,
+        position: args.overlay.position,
+        output: args.overlay.output.clone(),
         close_handle,
         method: args.overlay.method.clone().into(),
         save_path: args.output.clone(),
+        trim: args.trim,
         detection_theme,
         overlay_theme,
+        themes: args.misc.themes.clone(),
+        price_history: args
+            .overlay
+            .price_delta
+            .then(get_default_price_history_path),
+        ocr_mode: args.misc.ocr_mode,
+        substitutions,
+        market_prices: args.misc.market_prices,
+        price_stat: args.misc.price_stat,
+        font: args.misc.font.clone(),
+        fallback_fonts: args.misc.fallback_font.clone(),
+        log_rewards: log_rewards.to_vec(),
+        line_width: args.overlay.line_width,
+        corner_radius: args.overlay.corner_radius,
+        padding: args.overlay.padding,
+        background: args.overlay.background,
+        timeout: args.overlay.timeout.map(Duration::from_secs_f32),
+        fade_in: args.overlay.fade_in.map(Duration::from_secs_f32),
+        fade_out: args.overlay.fade_out.map(Duration::from_secs_f32),
+        interactive: args.overlay.interactive,
+        sort: args.overlay.sort,
+        layout: args.overlay.layout,
+        precision: args.overlay.precision,
+        relic,
+        force: args.misc.force,
+        reward_layout: args.misc.reward_layout,
+        output_format: args.output_format,
+        clipboard: args.clipboard,
+        debug_images: args.debug_images(),
+    })
+}
+
+/// Tracks whether the overlay is active, and asks it to close
+///
+/// activation is claimed with a compare-exchange, so two fast presses can't both start a scan
+#[derive(Debug, Clone, Default)]
+struct OverlayController {
+    active: Arc<AtomicBool>,
+    close_handle: Arc<AtomicBool>,
+}
+
+impl OverlayController {
+    /// Marks the overlay as active, returns false if it already was
+    fn activate(&self) -> bool {
+        let activated = self
+            .active
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+
+        if activated {
+            self.close_handle.store(false, Ordering::SeqCst);
+        }
+
+        activated
+    }
+
+    /// Asks the overlay to close, also stops a scan that hasn't shown the overlay yet
+    fn close(&self) {
+        self.close_handle.store(true, Ordering::SeqCst);
+    }
+
+    /// Marks the overlay as inactive, once it has closed or the scan was skipped
+    fn finish(&self) {
+        self.active.store(false, Ordering::SeqCst);
+    }
+
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    fn close_requested(&self) -> bool {
+        self.close_handle.load(Ordering::SeqCst)
+    }
+}
+
+/// Exit codes for `--now`, so scripts can branch on the outcome
+///
+/// 2 is left out since clap uses it for usage errors
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Exit {
+    Found = 0,
+    Error = 1,
+    NoRewards = 3,
+    NoForeground = 4,
+    CaptureFailed = 5,
+    Skipped = 6,
+    NotRewardScreen = 7,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+impl Exit {
+    /// Exit code for errors from scanning, [BinError::CaptureFailed] and why nothing was found
+    fn from_error(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<BinError>() {
+            Some(BinError::CaptureFailed(_) | BinError::RewardAreaClipped(_)) => {
+                Exit::CaptureFailed
+            }
+            Some(BinError::NoForeground(_)) => Exit::NoForeground,
+            Some(BinError::NoMatch) => Exit::NoRewards,
+            Some(BinError::NotRewardScreen) => Exit::NotRewardScreen,
+            _ => Exit::Error,
+        }
+    }
+}
+
+/// Fails with [BinError::CaptureFailed] if the image or window can't be read
+async fn capture(args: &Args, geometry_method: GeometryMethod) -> Result<DynamicImage, BinError> {
+    let capture_failed = |err: anyhow::Error| BinError::CaptureFailed(err.into());
+
+    let image = match (&args.image, &args.frame) {
+        (Some(image), _) => image::open(image).map_err(|err| capture_failed(err.into()))?,
+        (None, Some(frame)) => {
+            let frame = read_frame(frame, args.frame_size()).map_err(capture_failed)?;
+            let geometry = geometry_method
+                .get_active_window_geometry()
+                .map_err(capture_failed)?;
+            crop_to_geometry(
+                frame,
+                geometry,
+                args.geometry.capture_padding,
+                &args.misc.reward_layout,
+            )?
+        }
+        (None, None) => {
+            take_screenshot(
+                geometry_method,
+                args.geometry.capture_padding,
+                &args.misc.reward_layout,
+            )
+            .await?
+        }
     };
 
-    if !active_handle.load(Ordering::SeqCst) {
-        active_handle.store(true, Ordering::SeqCst);
-        bin::activate_overlay(image, &settings).await?;
-        active_handle.store(false, Ordering::SeqCst);
+    if args.misc.hdr {
+        return Ok(tonemap(image));
+    }
+
+    Ok(image)
+}
+
+/// Prints what a scan sees without matching items or showing the overlay, see `--diagnose`
+async fn diagnose(args: &Args) -> anyhow::Result<()> {
+    let geometry_method = args.geometry.method.clone();
+
+    if args.image.is_none() {
+        println!("Geometry method: {geometry_method:?}");
+
+        match geometry_method.clone().get_active_window_geometry() {
+            Ok(geometry) => println!("Window geometry: {geometry:?}"),
+            Err(err) => println!("Window geometry: {err:#}"),
+        }
+    }
+
+    let image = capture(args, geometry_method).await?;
+    let scale = args.misc.reward_layout.scale(&image)?;
+    println!(
+        "Image: {}x{}, scale: {scale:.3}",
+        image.width(),
+        image.height()
+    );
+
+    let scores = args
+        .misc
+        .themes
+        .detect_theme_scores(&image, scale, &args.misc.reward_layout);
+    println!("Theme scores:");
+
+    for (theme, score) in scores.iter().take(5) {
+        println!("  {:16} {score:.2}", theme.name);
+    }
+
+    let overlay_theme = scores
+        .first()
+        .filter(|(_, score)| *score > 0.0)
+        .map(|(theme, _)| (*theme).clone());
+    let detection_theme = detection_theme(&image, overlay_theme, args)?;
+    let selection = match &detection_theme {
+        Some(theme) => ThemeSelection::Fixed(theme),
+        None => ThemeSelection::Auto(&args.misc.themes),
+    };
+
+    let (names, theme) = reward_image_to_reward_names(
+        image,
+        selection,
+        &args.misc.reward_layout,
+        args.debug_images().as_ref(),
+    )?;
+    println!("Detection theme: {}", theme.name);
+    println!("OCR:");
+
+    if names.is_empty() {
+        println!("  nothing passed the theme filter");
+    }
+
+    for (i, (name, confidence)) in names.iter().enumerate() {
+        println!("  {i}: {name:?} ({confidence:.0}% confidence)");
     }
 
     Ok(())
 }
 
-async fn run_program(args: Args) -> anyhow::Result<()> {
-    let items = get_items(args.misc.prices.clone(), args.misc.filtered_items.clone()).await?;
-    let items = Arc::new(items);
-    let close_handle = Arc::new(AtomicBool::new(false));
-    let active_handle = Arc::new(AtomicBool::new(false));
+/// `log_rewards` are reward names from the log, used instead of OCR if they all match,
+/// `relic` is the relic from the log, shown above the rewards
+///
+/// returns None if activation was skipped, because warframe isn't focused
+/// or the overlay was closed before it was shown
+///
+/// the caller claims `controller` before calling this and finishes it afterwards
+async fn activate(
+    items: Arc<Items>,
+    controller: &OverlayController,
+    theme_cache: Arc<Mutex<Option<Theme>>>,
+    log_rewards: Vec<String>,
+    relic: Option<String>,
+    args: &Args,
+) -> anyhow::Result<Option<ScanResult<()>>> {
+    let geometry_method = args.geometry.method.clone();
 
-    if args.now {
-        activate(items, close_handle, active_handle, &args).await?;
+    if args.geometry.require_focus && args.image.is_none() && !args.headless {
+        let window = geometry_method
+            .clone()
+            .get_active_window()
+            .map_err(|err| BinError::CaptureFailed(err.into()))?;
+
+        if !window.matches(&args.geometry.window_name) {
+            debug!(
+                "Focused window is not {}: {:?} {:?}",
+                args.geometry.window_name, window.class, window.title
+            );
+
+            return Ok(None);
+        }
+    }
+
+    let start = Instant::now();
+    let image = capture(args, geometry_method).await?;
+
+    let mut timings = Timings::default();
+    timings.record("screenshot", start);
+
+    let scale = args.misc.reward_layout.scale(&image)?;
+
+    let use_theme_cache = args.overlay.theme.is_none() && !args.misc.no_theme_cache;
+
+    let cached_theme = if use_theme_cache {
+        theme_cache.lock().unwrap().clone()
+    } else {
+        None
+    };
+
+    // colors can shift, like switching HDR on, so a quick sample has to agree with the cache
+    let start = Instant::now();
+    let cached_theme = cached_theme.filter(|theme| {
+        let sampled = args
+            .misc
+            .themes
+            .sample_theme(&image, scale, &args.misc.reward_layout);
+        let matches = sampled.is_some_and(|sampled| sampled.name == theme.name);
+
+        if !matches {
+            debug!("Cached theme {} no longer matches the screen", theme.name);
+        }
+
+        matches
+    });
+    timings.record("cached theme check", start);
+
+    if controller.close_requested() {
+        debug!("Closed before the overlay was shown");
+        return Ok(None);
+    }
+
+    if let Some(theme) = cached_theme {
+        debug!("Using cached theme: {}", theme.name);
+
+        let settings = overlay_settings(
+            items.clone(),
+            controller.close_handle.clone(),
+            &image,
+            Some(theme),
+            &log_rewards,
+            relic.clone(),
+            args,
+        )?;
+
+        let result = bin::activate_overlay(
+            image.clone(),
+            &settings,
+            args.misc.timings.then(|| timings.clone()),
+        )
+        .await?;
+
+        // anything but a miss means the screen, not the theme, decided the result
+        if !matches!(result, ScanResult::NoMatch) {
+            return Ok(Some(result));
+        }
+
+        debug!("Cached theme matched no rewards, detecting theme again");
+        *theme_cache.lock().unwrap() = None;
+    }
+
+    let start = Instant::now();
+    let overlay_theme = args
+        .overlay
+        .theme
+        .map(|t| t.into())
+        .or_else(|| {
+            args.misc
+                .themes
+                .detect_theme(&image, scale, &args.misc.reward_layout)
+        })
+        .cloned();
+    timings.record("overlay theme detect", start);
+
+    let settings = overlay_settings(
+        items,
+        controller.close_handle.clone(),
+        &image,
+        overlay_theme.clone(),
+        &log_rewards,
+        relic,
+        args,
+    )?;
+    let timings = args.misc.timings.then_some(timings);
+
+    let result = bin::activate_overlay(image, &settings, timings).await?;
+
+    match &result {
+        ScanResult::Found(()) if use_theme_cache => {
+            if let Some(theme) = &overlay_theme
+                && let Err(err) = save_theme_name(
+                    get_default_theme_cache_path(),
+                    &theme.name,
+                    &args.theme_cache_key(),
+                )
+            {
+                warn!("Failed to save theme: {err}");
+            }
+
+            *theme_cache.lock().unwrap() = overlay_theme;
+        }
+        ScanResult::Found(()) => {}
+        ScanResult::NoForeground(theme) => warn!(
+            "Detected theme {theme} but couldn't read any rewards, try adjusting its thresholds"
+        ),
+        ScanResult::NoMatch => debug!("No rewards matched any items"),
+        ScanResult::NotRewardScreen => debug!("Not on the reward screen"),
+    }
+
+    Ok(Some(result))
+}
+
+/// Theme saved by the last run, see `--redetect`
+fn saved_theme(args: &Args) -> Option<Theme> {
+    if args.misc.redetect || args.misc.no_theme_cache {
+        return None;
+    }
+
+    let name = load_theme_name(get_default_theme_cache_path(), &args.theme_cache_key())?;
+    let theme = args.misc.themes.by_name(&name).cloned();
+
+    if theme.is_none() {
+        debug!("Saved theme {name} doesn't exist anymore");
+    }
+
+    theme
+}
+
+async fn load_items(args: &Args, mode: FetchMode) -> anyhow::Result<Items> {
+    let format = args.price_format();
 
-        return Ok(());
+    let items = match &args.misc.prices {
+        // price sheets are maintained by hand, so they're never fetched or cached
+        Some(prices) if format != PriceFormat::Json => {
+            Items::from_csv(std::fs::File::open(prices)?, format.delimiter())?
+        }
+        _ => {
+            get_items(
+                args.misc.prices.clone(),
+                args.misc.filtered_items.clone(),
+                args.misc.price_source,
+                args.misc.price_stat,
+                mode,
+                args.fetch_settings(),
+            )
+            .await?
+        }
+    };
+
+    Ok(items.with_max_relative_distance(args.misc.match_threshold))
+}
+
+/// Prints `--rank-relics` by expected platinum at `--refinement`, see `Relics::rank_by_ev`
+async fn rank_relics(args: &Args) -> anyhow::Result<()> {
+    let items = load_items(args, args.fetch_mode()).await?;
+    let relics = get_relics(
+        args.misc.filtered_items.clone(),
+        args.fetch_mode(),
+        args.fetch_settings(),
+    )
+    .await?;
+
+    let owned = args
+        .rank_relics
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let (ranked, missing) = relics.rank_by_ev(&owned, &items, args.misc.refinement);
+
+    for name in missing {
+        warn!("Missing relic: {name}");
+    }
+
+    let width = ranked
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+
+    for (name, platinum) in ranked {
+        println!("{name:width$}  {platinum:>6.1}p");
     }
 
+    Ok(())
+}
+
+/// Items behind a lock so they can be swapped out while the shortcut and watcher are running
+type SharedItems = Arc<RwLock<Arc<Items>>>;
+
+/// Reloads items every time SIGHUP is received
+async fn reload_on_signal(items: SharedItems, args: Arc<Args>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    // reloading is for picking up new prices, so skip the cache unless offline
+    let mode = match args.fetch_mode() {
+        FetchMode::Offline => FetchMode::Offline,
+        _ => FetchMode::Refresh,
+    };
+
+    while hangup.recv().await.is_some() {
+        debug!("Reloading items");
+
+        match load_items(&args, mode).await {
+            Ok(new_items) if new_items.is_empty() => {
+                warn!("Reloading found no prices, keeping the current items")
+            }
+            Ok(new_items) => *items.write().unwrap() = Arc::new(new_items),
+            Err(err) => error!("Failed to reload items, keeping the current items: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_now(args: Args) -> anyhow::Result<Option<ScanResult<()>>> {
+    let items = load_items(&args, args.fetch_mode()).await?;
+
+    activate(
+        Arc::new(items),
+        &OverlayController::default(),
+        Arc::new(Mutex::new(saved_theme(&args))),
+        vec![],
+        None,
+        &args,
+    )
+    .await
+}
+
+async fn run_program(args: Args) -> anyhow::Result<()> {
+    let items = load_items(&args, args.fetch_mode()).await?;
+    let items = Arc::new(items);
+    let controller = OverlayController::default();
+    let theme_cache = Arc::new(Mutex::new(saved_theme(&args)));
+
+    let headless = args.headless;
     let args = Arc::new(args);
     let shortcut_args = args.shortcut.clone();
+    let items: SharedItems = Arc::new(RwLock::new(items));
 
-    let callback_items = items.clone();
-    let callback_close_handle = close_handle.clone();
-    let callback_active_handle = active_handle.clone();
+    let reload_items = items.clone();
+    let reload_args = args.clone();
+    let watcher_args = args.clone();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        if let Err(err) = rt.block_on(reload_on_signal(reload_items, reload_args)) {
+            error!("{err}");
+        }
+    });
+
+    let callback_items = items;
+    let callback_controller = controller.clone();
 
-    let callback = move || {
+    let callback = move |log_rewards: Vec<String>, relic: Option<String>| {
         let args = args.clone();
-        let items = callback_items.clone();
-        let close_handle = callback_close_handle.clone();
-        let active_handle = callback_active_handle.clone();
+        let items = callback_items.read().unwrap().clone();
+        let controller = callback_controller.clone();
+        let theme_cache = theme_cache.clone();
 
         debug!("Attempting to activate");
 
-        if active_handle.load(Ordering::SeqCst) {
+        if !controller.activate() {
             debug!("Already active, closing overlay");
-            close_handle.store(true, Ordering::SeqCst);
+            controller.close();
             return;
         }
 
@@ -100,75 +588,241 @@ async fn run_program(args: Args) -> anyhow::Result<()> {
             let rt = tokio::runtime::Runtime::new().unwrap();
             let result = rt.block_on(activate(
                 items,
-                close_handle.clone(),
-                active_handle.clone(),
+                &controller,
+                theme_cache,
+                log_rewards,
+                relic,
                 &args,
             ));
 
+            controller.finish();
+
             if let Err(err) = result {
-                active_handle.store(false, Ordering::SeqCst);
-                close_handle.store(true, Ordering::SeqCst);
+                controller.close();
                 error!("{err}");
             }
         });
     };
 
     let shortcut_callback = callback.clone();
-    let shortcut = std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-
-        let settings = ShortcutSettings {
-            id: &shortcut_args.id,
-            preferred_trigger: &shortcut_args.trigger,
-        };
-
-        match shortcut_args.method {
-            ArgShortcutMethod::Portal => {
-                rt.block_on(bin::portal_shortcut(settings, shortcut_callback)) //
-            }
-            ArgShortcutMethod::X11 => {
-                rt.block_on(bin::x11_shortcut(settings, shortcut_callback)) //
+    // shortcuts need a display server, headless only reacts to the log
+    let shortcut = (!headless).then(|| {
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let ids = shortcut_args.ids();
+
+            // every trigger activates the same way, each needs its own id for the portal
+            let bindings = shortcut_args
+                .trigger
+                .iter()
+                .zip(&ids)
+                .map(|(trigger, id)| {
+                    let settings = ShortcutSettings {
+                        id,
+                        preferred_trigger: trigger,
+                        mode: shortcut_args.mode,
+                    };
+                    let callback = shortcut_callback.clone();
+
+                    ShortcutBinding::new(settings, move || callback(vec![], None))
+                })
+                .collect();
+
+            match shortcut_args.method {
+                ArgShortcutMethod::Portal => rt.block_on(bin::portal_shortcuts(bindings)),
+                ArgShortcutMethod::X11 => rt.block_on(bin::x11_shortcuts(bindings)),
             }
-        }
+        })
     });
 
     let watcher_callback = callback;
     let watcher = std::thread::spawn(move || {
-        let file = get_default_ee_log_path();
+        let files = if watcher_args.misc.ee_log.is_empty() {
+            vec![get_default_ee_log_path()]
+        } else {
+            watcher_args.misc.ee_log.clone()
+        };
+
+        let settings = WatcherSettings {
+            poll_interval: Duration::from_millis(watcher_args.misc.poll_interval),
+            debounce: Duration::from_millis(watcher_args.misc.debounce),
+        };
 
-        log_watcher(
-            file,
-            || {
-                if active_handle.load(Ordering::SeqCst) {
-                    close_handle.store(true, Ordering::SeqCst);
+        log_watcher_many(
+            &files,
+            settings,
+            |log_rewards, relic| {
+                if controller.is_active() {
+                    controller.close();
                 }
 
-                std::thread::sleep(std::time::Duration::from_millis(1500));
+                std::thread::sleep(settings.debounce);
 
-                watcher_callback();
-            },
-            || {
-                close_handle.store(true, Ordering::SeqCst);
+                watcher_callback(log_rewards, relic);
             },
+            || controller.close(),
         )
     });
 
-    shortcut.join().unwrap()?;
+    if let Some(shortcut) = shortcut {
+        shortcut.join().unwrap()?;
+    }
+
     watcher.join().unwrap()?;
 
     Ok(())
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     env_logger::init();
     let args = Args::parse();
 
-    println!("{}", toml::to_string_pretty(&args).unwrap());
+    if let Some(name) = &args.export_theme {
+        let Some(theme) = args.find_theme(name) else {
+            Args::error(
+                clap::error::ErrorKind::InvalidValue,
+                format!("Theme not found: {name}"),
+            );
+        };
+
+        println!("{}", serde_json::to_string_pretty(&theme).unwrap());
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(name) = &args.save_preset {
+        let path = get_default_presets_path();
+
+        return match args.save_preset(&path, name) {
+            Ok(()) => {
+                println!("Saved preset {name} to {}", path.display());
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                error!("Failed to save preset: {err:#}");
+                Exit::Error.into()
+            }
+        };
+    }
+
+    if !args.rank_relics.is_empty() {
+        return match rank_relics(&args).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                error!("{err:#}");
+                Exit::Error.into()
+            }
+        };
+    }
+
+    if args.list_outputs {
+        return match bin::overlay::backend::wayland::list_outputs() {
+            Ok(outputs) => {
+                outputs.iter().for_each(|output| println!("{output}"));
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                error!("{err:#}");
+                Exit::Error.into()
+            }
+        };
+    }
+
+    debug!("{}", toml::to_string_pretty(&args).unwrap());
+
+    if args.diagnose {
+        return match diagnose(&args).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                error!("{err:#}");
+                Exit::Error.into()
+            }
+        };
+    }
+
+    if args.now {
+        let exit = match run_now(args).await {
+            Ok(Some(ScanResult::Found(()))) => Exit::Found,
+            Ok(Some(ScanResult::NoMatch)) => Exit::NoRewards,
+            Ok(Some(ScanResult::NoForeground(_))) => Exit::NoForeground,
+            Ok(Some(ScanResult::NotRewardScreen)) => Exit::NotRewardScreen,
+            Ok(None) => Exit::Skipped,
+            Err(err) => {
+                error!("{err:#}");
+                Exit::from_error(&err)
+            }
+        };
+
+        return exit.into();
+    }
 
     let Err(err) = run_program(args).await else {
-        return;
+        return ExitCode::SUCCESS;
     };
 
     Args::error(clap::error::ErrorKind::InvalidValue, err);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn hammering_activate_and_close() {
+        let controller = OverlayController::default();
+        // overlays shown at the same time, and the most there ever were
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let activations = Arc::new(AtomicUsize::new(0));
+
+        let threads = (0..8)
+            .map(|_| {
+                let controller = controller.clone();
+                let running = running.clone();
+                let peak = peak.clone();
+                let activations = activations.clone();
+
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        // like the shortcut callback, a press while active closes instead
+                        if !controller.activate() {
+                            controller.close();
+                            continue;
+                        }
+
+                        let shown = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(shown, Ordering::SeqCst);
+                        activations.fetch_add(1, Ordering::SeqCst);
+
+                        assert!(controller.is_active());
+                        std::thread::yield_now();
+
+                        running.fetch_sub(1, Ordering::SeqCst);
+
+                        controller.finish();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(activations.load(Ordering::SeqCst) > 0);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+        assert!(!controller.is_active());
+
+        // a new activation starts without the close requested by the last press
+        controller.close();
+        assert!(controller.activate());
+        assert!(!controller.close_requested());
+        assert!(!controller.activate());
+
+        controller.finish();
+        assert!(!controller.is_active());
+    }
+}
@@ -12,8 +12,8 @@ fn main() -> anyhow::Result<()> {
 
     bin::watcher::log_watcher(
         file,
-        || {
-            println!("e");
+        |rewards, relic| {
+            println!("e {relic:?} {rewards:?}");
         },
         || {},
     )?;
@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use colored::{Color, Colorize};
+use lib::ocr::{OcrMode, Substitutions, reward_image_to_items};
+use lib::theme::ThemeSelection;
+use lib::timings::Timings;
+use lib::util::RewardLayout;
+
+/// Screenshots of reward screens, next to `expected.json`
+/// which maps each file name to its reward names in order, `null` for unknown rewards
+///
+/// ```json
+/// { "4-rewards.png": ["Ash Prime Systems Blueprint", "Forma Blueprint", null, "Lex Prime Barrel"] }
+/// ```
+///
+/// `tests/expected_rewards.rs` fails `cargo test` when any of them stop matching
+const REWARDS_DIR: &str = "./test-images/rewards";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let dir = std::env::args().nth(1).unwrap_or(REWARDS_DIR.into());
+    let dir = std::path::Path::new(&dir);
+
+    let expected: BTreeMap<String, Vec<Option<String>>> =
+        serde_json::from_reader(std::fs::File::open(dir.join("expected.json"))?)?;
+
+    let items = bin::cache::get_items(
+        None,
+        None,
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    )
+    .await?;
+    let substitutions = Substitutions::default();
+
+    let mut totals = HashMap::<String, Duration>::new();
+    let mut correct = 0;
+    let mut total = 0;
+
+    for (file, expected) in &expected {
+        let image = image::open(dir.join(file))?;
+        let mut timings = Timings::default();
+
+        let (found, theme) = reward_image_to_items(
+            &items,
+            image,
+            ThemeSelection::default(),
+            &RewardLayout::default(),
+            OcrMode::default(),
+            &substitutions,
+            None,
+            &mut timings,
+        )?;
+
+        let found = found.unwrap_or_default();
+        let found = found
+            .iter()
+            .map(|item| item.as_ref().map(|item| item.name.as_str()))
+            .collect::<Vec<_>>();
+
+        let matches = expected
+            .iter()
+            .zip(&found)
+            .filter(|(expected, found)| expected.as_deref() == **found)
+            .count();
+
+        correct += matches;
+        total += expected.len().max(found.len());
+
+        let color = if matches == expected.len() && found.len() == expected.len() {
+            Color::BrightGreen
+        } else {
+            Color::BrightRed
+        };
+
+        println!(
+            "{}",
+            format!("{file}: {matches}/{} ({})", expected.len(), theme.name).color(color)
+        );
+
+        if color == Color::BrightRed {
+            println!("  expected: {expected:?}");
+            println!("  found:    {found:?}");
+        }
+
+        for (stage, duration) in timings.stages() {
+            // per part stages are already summed into "ocr"
+            if !stage.starts_with("ocr part") {
+                *totals.entry(stage.clone()).or_default() += *duration;
+            }
+        }
+    }
+
+    let mut average = Timings::default();
+    let runs = expected.len().max(1) as u32;
+
+    for stage in [
+        "scale/theme detect",
+        "part extraction",
+        "ocr",
+        "item matching",
+    ] {
+        if let Some(duration) = totals.get(stage) {
+            average.add(stage, *duration / runs);
+        }
+    }
+
+    println!();
+    println!("Average over {runs} images");
+    print!("{average}");
+    println!();
+    println!(
+        "Correct: {correct}/{total} ({:.1}%)",
+        correct as f32 / total.max(1) as f32 * 100.0
+    );
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use bin::overlay::backend::{OverlayBackend, OverlayMethod, get_backend};
+use bin::overlay::femtovg::{Canvas, Color, Paint, Path, Renderer};
+use bin::overlay::{
+    Error, OverlayAnchor, OverlayConf, OverlayMargin, OverlayRenderer, OverlayTime, color_from_hsl,
+    load_font
+};
+use lib::theme::{DEFAULT_THEMES_SLICE, Theme};
+
+const ROW_HEIGHT: f32 = 40.0;
+const NAME_WIDTH: f32 = 220.0;
+const SWATCH_WIDTH: f32 = 80.0;
+const SAMPLE_WIDTH: f32 = 300.0;
+const FONT_SIZE: f32 = 20.0;
+
+struct ContactSheet<'a> {
+    themes: &'a [Theme],
+}
+
+impl<T: Renderer> OverlayRenderer<T> for ContactSheet<'_> {
+    fn setup(&mut self, canvas: &mut Canvas<T>, _: &OverlayTime) -> Result<(), Error> {
+        load_font(canvas, None)?;
+        Ok(())
+    }
+
+    fn draw(&mut self, canvas: &mut Canvas<T>, _: &OverlayTime) -> Result<(), Error> {
+        let (width, height) = (canvas.width(), canvas.height());
+        canvas.clear_rect(0, 0, width, height, Color::rgb(0, 0, 0));
+
+        let text = Paint::color(Color::white()).with_font_size(FONT_SIZE);
+        let baseline = (ROW_HEIGHT + FONT_SIZE) / 2.0 - 4.0;
+
+        for (i, theme) in self.themes.iter().enumerate() {
+            let y = ROW_HEIGHT * i as f32;
+            let primary = color_from_hsl(theme.primary);
+            let secondary = color_from_hsl(theme.secondary);
+
+            canvas.fill_text(8.0, y + baseline, &theme.name, &text)?;
+
+            for (j, color) in [primary, secondary].into_iter().enumerate() {
+                let mut swatch = Path::new();
+                let x = NAME_WIDTH + SWATCH_WIDTH * j as f32;
+                swatch.rect(x + 4.0, y + 4.0, SWATCH_WIDTH - 8.0, ROW_HEIGHT - 8.0);
+                canvas.fill_path(&swatch, &Paint::color(color));
+            }
+
+            // how text looks in the overlay with this theme
+            let x = NAME_WIDTH + SWATCH_WIDTH * 2.0 + 8.0;
+            let label = canvas.fill_text(
+                x,
+                y + baseline,
+                "Platinum: ",
+                &text.clone().with_color(primary),
+            )?;
+            canvas.fill_text(
+                x + label.width(),
+                y + baseline,
+                "42",
+                &text.clone().with_color(secondary),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders every default theme's name, primary and secondary swatch and sample text
+/// into one image, to check themes added to `DEFAULT_THEMES_SLICE` at a glance
+///
+/// cargo run --example theme_contact_sheet -- [output path]
+fn main() -> anyhow::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or("./test-images/themes.png".into());
+
+    let conf = OverlayConf {
+        anchor: OverlayAnchor::TopLeft,
+        margin: OverlayMargin::default(),
+        width: (NAME_WIDTH + SWATCH_WIDTH * 2.0 + SAMPLE_WIDTH) as u32,
+        height: (ROW_HEIGHT * DEFAULT_THEMES_SLICE.len() as f32) as u32,
+        save_path: Some(path.clone().into()),
+        trim: false,
+        position: None,
+        output: None,
+        timeout: None,
+        fade_in: None,
+        fade_out: None,
+        interactive: false,
+        close_handle: Arc::new(AtomicBool::new(false)),
+    };
+
+    let mut backend =
+        get_backend(OverlayMethod::Image).ok_or_else(|| anyhow::anyhow!("Backend not found"))?;
+
+    backend.run(
+        conf,
+        ContactSheet {
+            themes: DEFAULT_THEMES_SLICE,
+        },
+    )?;
+
+    println!("Saved {} themes to {path}", DEFAULT_THEMES_SLICE.len());
+
+    Ok(())
+}
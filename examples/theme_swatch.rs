@@ -0,0 +1,67 @@
+use colored::Colorize;
+use image::Rgb;
+use lib::theme::{DEFAULT_THEMES, threshold_filter_custom, threshold_margins};
+
+fn parse_hex(hex: &str) -> anyhow::Result<Rgb<u8>> {
+    let hex = hex.trim_start_matches('#');
+
+    if hex.len() != 6 {
+        anyhow::bail!("Expected a color like #eec169, got {hex}");
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+
+    Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+/// Prints whether a color passes a theme's filter, and by how much each of H/S/L is off
+///
+/// cargo run --example theme_swatch -- "#eec169" Baruuk
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    let (Some(hex), Some(name)) = (args.next(), args.next()) else {
+        anyhow::bail!("Usage: theme_swatch <hex color> <theme name>");
+    };
+
+    let color = parse_hex(&hex)?;
+    let theme = DEFAULT_THEMES
+        .by_name(&name)
+        .ok_or_else(|| anyhow::anyhow!("Theme not found: {name}"))?;
+
+    let filters = [
+        ("primary", theme.primary, theme.primary_threshold),
+        ("secondary", theme.secondary, theme.secondary_threshold),
+    ];
+
+    for (filter, base, threshold) in filters {
+        let [h, s, l] = threshold;
+        let accepted = threshold_filter_custom(base, color, h, s, l);
+
+        let result = if accepted {
+            "accepted".bright_green()
+        } else {
+            "rejected".bright_red()
+        };
+
+        println!("{filter}: {result}");
+
+        let margins = threshold_margins(base, color, threshold);
+
+        for ((axis, margin), threshold) in ["H", "S", "L"].iter().zip(margins).zip(threshold) {
+            let line = if margin < 0.0 {
+                format!(
+                    "  {axis}: in range by {:.4} (threshold {threshold})",
+                    -margin
+                )
+                .green()
+            } else {
+                format!("  {axis}: out of range by {margin:.4} (threshold {threshold})").red()
+            };
+
+            println!("{line}");
+        }
+    }
+
+    Ok(())
+}
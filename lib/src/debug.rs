@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::RgbImage;
+use log::{debug, warn};
+
+/// Where images from each scan step are written, for debugging failed scans
+#[derive(Debug, Clone)]
+pub struct DebugImages {
+    pub dir: PathBuf,
+    /// If more than 0, each scan gets its own timestamped folder
+    /// and only this many of the latest are kept
+    pub keep: usize,
+}
+
+impl DebugImages {
+    /// Folder for the images of a new scan, a new timestamped one if old scans are kept
+    pub fn begin_scan(&self) -> PathBuf {
+        if self.keep == 0 {
+            return self.dir.clone();
+        }
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        if let Err(err) = remove_old_scans(self) {
+            warn!("Failed to remove old debug scans: {err}");
+        }
+
+        self.dir.join(format!("scan-{millis}"))
+    }
+}
+
+/// Removes the oldest scan folders so that a new one fits within [DebugImages::keep]
+fn remove_old_scans(images: &DebugImages) -> std::io::Result<()> {
+    if !images.dir.exists() {
+        return Ok(());
+    }
+
+    let mut scans = std::fs::read_dir(&images.dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("scan-"))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+
+    // names are timestamps of the same length, so they sort oldest first
+    scans.sort();
+
+    let remove = (scans.len() + 1).saturating_sub(images.keep);
+
+    for scan in scans.into_iter().take(remove) {
+        debug!("Removing old debug scan {}", scan.display());
+        std::fs::remove_dir_all(scan)?;
+    }
+
+    Ok(())
+}
+
+/// Writes `image` to `scan_dir` from [DebugImages::begin_scan], nothing is written if it's none
+pub fn save_image(scan_dir: Option<&Path>, name: &str, image: &RgbImage) {
+    let Some(scan_dir) = scan_dir else {
+        return;
+    };
+
+    let path = scan_dir.join(name);
+
+    let result = std::fs::create_dir_all(scan_dir)
+        .map_err(image::ImageError::IoError)
+        .and_then(|_| image.save(&path));
+
+    if let Err(err) = result {
+        warn!("Failed to write debug image {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+    use crate::ocr::reward_image_to_parts;
+    use crate::theme::{DEFAULT_THEMES_SLICE, ThemeSelection};
+    use crate::timings::Timings;
+    use crate::util::RewardLayout;
+
+    fn scan(debug_images: Option<&DebugImages>) {
+        let image = DynamicImage::new_rgb8(1920, 1080);
+        let theme = &DEFAULT_THEMES_SLICE[0];
+
+        reward_image_to_parts(
+            image,
+            ThemeSelection::Fixed(theme),
+            &RewardLayout::default(),
+            debug_images,
+            &mut Timings::default(),
+        )
+        .unwrap();
+    }
+
+    fn files(dir: &Path) -> usize {
+        std::fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    #[test]
+    fn nothing_is_written_without_debug_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = DebugImages {
+            dir: dir.path().join("debug"),
+            keep: 0,
+        };
+
+        scan(None);
+        assert_eq!(files(&images.dir), 0);
+
+        scan(Some(&images));
+        assert!(images.dir.join("filtered.png").exists());
+    }
+
+    #[test]
+    fn only_the_latest_scans_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = DebugImages {
+            dir: dir.path().join("debug"),
+            keep: 2,
+        };
+
+        for _ in 0..4 {
+            scan(Some(&images));
+            // scan folders are named by the millisecond
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        assert_eq!(files(&images.dir), 2);
+    }
+}
@@ -0,0 +1,91 @@
+use image::DynamicImage;
+use log::debug;
+use palette::{FromColor, Hsl, Srgb};
+use rayon::iter::ParallelIterator;
+use rayon::slice::ParallelSliceMut;
+
+/// Fraction of pixels ignored at each end when finding the range of the image
+const PERCENTILE: f32 = 0.01;
+
+/// Lightness range at least this close to 0..1 counts as SDR
+const SDR_LIGHTNESS_MARGIN: f32 = 0.02;
+
+/// Saturation reaching this counts as SDR, HDR captures look washed out
+const SDR_SATURATION: f32 = 0.9;
+
+const BINS: usize = 256;
+
+/// Value below which `fraction` of the histogram falls
+fn percentile(histogram: &[u32; BINS], total: u32, fraction: f32) -> f32 {
+    let target = (total as f32 * fraction) as u32;
+    let mut seen = 0;
+
+    for (i, count) in histogram.iter().enumerate() {
+        seen += count;
+
+        if seen > target {
+            return i as f32 / (BINS - 1) as f32;
+        }
+    }
+
+    1.0
+}
+
+fn to_hsl(pixel: &[u8]) -> Hsl {
+    let rgb = Srgb::new(pixel[0], pixel[1], pixel[2]).into_format::<f32>();
+    Hsl::from_color(rgb)
+}
+
+/// Stretches lightness and saturation of an HDR capture back to the range themes expect,
+/// run before theme detection and filtering
+///
+/// does nothing to images that already use the full range, so SDR captures are unchanged
+pub fn tonemap(image: DynamicImage) -> DynamicImage {
+    let mut image = image.into_rgb8();
+
+    let mut lightness = [0; BINS];
+    let mut saturation = [0; BINS];
+
+    for pixel in image.pixels() {
+        let hsl = to_hsl(&pixel.0);
+        lightness[(hsl.lightness * (BINS - 1) as f32) as usize] += 1;
+        saturation[(hsl.saturation * (BINS - 1) as f32) as usize] += 1;
+    }
+
+    let total = image.width() * image.height();
+    let low = percentile(&lightness, total, PERCENTILE);
+    let high = percentile(&lightness, total, 1.0 - PERCENTILE);
+    let max_saturation = percentile(&saturation, total, 1.0 - PERCENTILE);
+
+    let stretch_lightness =
+        (low > SDR_LIGHTNESS_MARGIN || high < 1.0 - SDR_LIGHTNESS_MARGIN) && high > low;
+    let stretch_saturation = max_saturation < SDR_SATURATION && max_saturation > 0.0;
+
+    debug!("Tonemap: lightness {low}..{high}, saturation ..{max_saturation}");
+
+    if !stretch_lightness && !stretch_saturation {
+        return DynamicImage::ImageRgb8(image);
+    }
+
+    let (low, high) = if stretch_lightness {
+        (low, high)
+    } else {
+        (0.0, 1.0)
+    };
+    let saturation_gain = if stretch_saturation {
+        SDR_SATURATION / max_saturation
+    } else {
+        1.0
+    };
+
+    image.par_chunks_mut(3).for_each(|pixel| {
+        let mut hsl = to_hsl(pixel);
+        hsl.lightness = ((hsl.lightness - low) / (high - low)).clamp(0.0, 1.0);
+        hsl.saturation = (hsl.saturation * saturation_gain).clamp(0.0, 1.0);
+
+        let rgb = Srgb::from_color(hsl).into_format::<u8>();
+        pixel.copy_from_slice(&[rgb.red, rgb.green, rgb.blue]);
+    });
+
+    DynamicImage::ImageRgb8(image)
+}
@@ -1,7 +1,11 @@
 use thiserror::Error;
 
+pub mod debug;
+pub mod hdr;
+pub mod market;
 pub mod ocr;
 pub mod theme;
+pub mod timings;
 pub mod util;
 pub mod wfinfo;
 
@@ -9,12 +13,24 @@ pub mod wfinfo;
 pub enum Error {
     #[error("Unknown Theme")]
     UnknownTheme,
-    #[error("Invalid size, height > width: {1} > {0}")]
-    InvalidSize(u32, u32),
+    #[error("No themes to detect from")]
+    NoThemes,
+    #[error("Invalid hex color {0:?}, expected #rrggbb")]
+    InvalidHexColor(String),
     #[error("Invalid Image Format")]
     InvalidImageFormat,
+    #[error("Reward crop too small: {0}x{1}, check scale/geometry")]
+    PartTooSmall(u32, u32),
     #[error(transparent)]
     InitializeError(#[from] tesseract::InitializeError),
+    #[error("Invalid price row on line {line}: {row:?}, expected name,platinum,ducats")]
+    InvalidPriceRow { line: usize, row: String },
+    #[error("Item database version {found} doesn't match {expected}")]
+    ItemsVersionMismatch { found: u32, expected: u32 },
+    #[error("Item database was built from other files: {found:?}")]
+    ItemsSourcesMismatch { found: Vec<std::path::PathBuf> },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
     #[error(transparent)]
     SerdeJsonError(#[from] serde_json::Error),
     #[error(transparent)]
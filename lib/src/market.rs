@@ -0,0 +1,261 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::wfinfo::Price;
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrdersResponse {
+    payload: OrdersPayload,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrdersPayload {
+    orders: Vec<Order>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Order {
+    platinum: f32,
+    order_type: String,
+    user: OrderUser,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrderUser {
+    status: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatisticsResponse {
+    payload: StatisticsPayload,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatisticsPayload {
+    statistics_closed: ClosedStatistics,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ClosedStatistics {
+    #[serde(rename = "48hours")]
+    hours_48: Vec<Statistic>,
+}
+
+/// Sales in one hour
+#[derive(Debug, Clone, Deserialize)]
+struct Statistic {
+    median: f32,
+}
+
+/// Where [crate::wfinfo::Item::platinum] comes from
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PriceSource {
+    /// Averages from wfinfo, updated daily
+    #[default]
+    WfInfo,
+    /// In game sell orders from warframe.market, see [PriceStat]
+    WarframeMarket,
+}
+
+/// Which statistic is used as the price, the 48 hour median of sales by default,
+/// the rest are taken from the current in game sell orders
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PriceStat {
+    /// Median of the hourly sale medians over the last 48 hours
+    #[default]
+    Median48h,
+    Avg,
+    Median,
+    Low,
+    /// Lowest to median sell order, shown as a range like 40–55
+    Spread,
+}
+
+impl PriceStat {
+    /// `prices` are expected to be sorted
+    fn of(self, prices: &[f32]) -> Option<Price> {
+        if prices.is_empty() {
+            return None;
+        }
+
+        let median = if prices.len().is_multiple_of(2) {
+            (prices[prices.len() / 2 - 1] + prices[prices.len() / 2]) / 2.0
+        } else {
+            prices[prices.len() / 2]
+        };
+
+        let price = match self {
+            PriceStat::Median48h | PriceStat::Median => Price::Avg(median),
+            PriceStat::Avg => Price::Avg(prices.iter().sum::<f32>() / prices.len() as f32),
+            PriceStat::Low => Price::Avg(prices[0]),
+            PriceStat::Spread if prices[0] == median => Price::Avg(median),
+            PriceStat::Spread => Price::Range {
+                lo: prices[0],
+                hi: median,
+            },
+        };
+
+        Some(price)
+    }
+}
+
+impl Display for PriceStat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceStat::Median48h => write!(f, "median48h"),
+            PriceStat::Avg => write!(f, "avg"),
+            PriceStat::Median => write!(f, "median"),
+            PriceStat::Low => write!(f, "low"),
+            PriceStat::Spread => write!(f, "spread"),
+        }
+    }
+}
+
+/// Live prices from warframe.market, one request per item
+#[derive(Clone)]
+pub struct WarframeMarket {
+    client: reqwest::Client,
+}
+
+impl WarframeMarket {
+    pub fn new() -> crate::Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(5))
+            .build()?;
+
+        Ok(Self { client })
+    }
+
+    /// `stat` of the sales from `/statistics` or the sell orders from players that are in game,
+    /// none if the item isn't listed or nobody is selling it
+    pub async fn fetch_price(&self, name: &str, stat: PriceStat) -> crate::Result<Option<Price>> {
+        if stat == PriceStat::Median48h {
+            let statistics = self
+                .fetch_item::<StatisticsResponse>(name, "statistics")
+                .await?;
+
+            return Ok(statistics.and_then(median_48h));
+        }
+
+        let Some(orders) = self.fetch_item::<OrdersResponse>(name, "orders").await? else {
+            return Ok(None);
+        };
+
+        let orders = orders.payload.orders;
+
+        let mut prices = orders
+            .into_iter()
+            .filter(|order| order.order_type == "sell" && order.user.status == "ingame")
+            .map(|order| order.platinum)
+            .collect::<Vec<_>>();
+
+        prices.sort_by(f32::total_cmp);
+
+        Ok(stat.of(&prices))
+    }
+
+    /// Fetches `/items/{url_name}/{endpoint}`, none if the item isn't listed
+    async fn fetch_item<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        endpoint: &str,
+    ) -> crate::Result<Option<T>> {
+        let url_name = url_name(name);
+
+        // wfinfo drops "Blueprint" from some part names, warframe.market doesn't
+        match self.fetch(&url_name, endpoint).await? {
+            Some(response) => Ok(Some(response)),
+            None => self.fetch(&format!("{url_name}_blueprint"), endpoint).await,
+        }
+    }
+
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        url_name: &str,
+        endpoint: &str,
+    ) -> crate::Result<Option<T>> {
+        let response = self
+            .client
+            .get(format!(
+                "https://api.warframe.market/v1/items/{url_name}/{endpoint}"
+            ))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+}
+
+fn median_48h(statistics: StatisticsResponse) -> Option<Price> {
+    let mut medians = statistics
+        .payload
+        .statistics_closed
+        .hours_48
+        .into_iter()
+        .map(|statistic| statistic.median)
+        .collect::<Vec<_>>();
+
+    medians.sort_by(f32::total_cmp);
+
+    PriceStat::Median48h.of(&medians)
+}
+
+/// "Ash Prime Neuroptics" -> "ash_prime_neuroptics"
+fn url_name(name: &str) -> String {
+    name.split_ascii_whitespace()
+        .map(|word| word.replace('&', "and").to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread_is_lowest_to_median() {
+        let prices = [40.0, 45.0, 55.0, 70.0, 300.0];
+
+        assert_eq!(PriceStat::Low.of(&prices), Some(Price::Avg(40.0)));
+        assert_eq!(PriceStat::Median.of(&prices), Some(Price::Avg(55.0)));
+        assert_eq!(
+            PriceStat::Spread.of(&prices),
+            Some(Price::Range { lo: 40.0, hi: 55.0 })
+        );
+        assert_eq!(PriceStat::Spread.of(&[40.0, 40.0]), Some(Price::Avg(40.0)));
+        assert_eq!(PriceStat::Spread.of(&[]), None);
+    }
+
+    #[test]
+    fn median_of_48_hour_statistics() {
+        let statistics = r#"{"payload": {
+            "statistics_closed": {
+                "48hours": [
+                    {"datetime": "2026-10-14T10:00:00.000+00:00", "volume": 3, "median": 50},
+                    {"datetime": "2026-10-14T11:00:00.000+00:00", "volume": 1, "median": 40.5},
+                    {"datetime": "2026-10-14T12:00:00.000+00:00", "volume": 2, "median": 300}
+                ],
+                "90days": []
+            },
+            "statistics_live": {"48hours": [], "90days": []}
+        }}"#;
+
+        let statistics = serde_json::from_str::<StatisticsResponse>(statistics).unwrap();
+        assert_eq!(median_48h(statistics), Some(Price::Avg(50.0)));
+
+        let empty = r#"{"payload": {"statistics_closed": {"48hours": []}}}"#;
+        let empty = serde_json::from_str::<StatisticsResponse>(empty).unwrap();
+        assert_eq!(median_48h(empty), None);
+    }
+}
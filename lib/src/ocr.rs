@@ -1,34 +1,47 @@
-use image::DynamicImage;
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbImage, RgbaImage};
 use log::debug;
-use tesseract::Tesseract;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use tesseract::{PageSegMode, Tesseract};
 
-use crate::theme::{DEFAULT_THEMES, Theme, Themes};
+use crate::debug::DebugImages;
+use crate::theme::{Theme, ThemeSelection, Themes};
+use crate::timings::Timings;
 use crate::util::{
-    FILTER_BACKGROUND, FILTER_FOREGROUND, PIXEL_REWARD_HEIGHT, PIXEL_REWARD_LINE_HEIGHT, PIXEL_REWARD_WIDTH, PIXEL_REWARD_Y, get_scale
+    FILTER_BACKGROUND, FILTER_FOREGROUND, PIXEL_REWARD_LINE_HEIGHT, PIXEL_REWARD_WIDTH, RewardLayout
 };
 use crate::wfinfo::{Item, Items};
 
-pub fn extract_parts(image: &DynamicImage, theme: &Theme, scale: f32) -> Vec<DynamicImage> {
-    // image.save("input.png").unwrap();
-    let width = image.width() as f32;
-    let reward_y = PIXEL_REWARD_Y * scale;
-    let reward_width = PIXEL_REWARD_WIDTH * scale;
-    let reward_height = PIXEL_REWARD_HEIGHT * scale;
-    let reward_line = PIXEL_REWARD_LINE_HEIGHT * scale;
+/// Smallest part crop that can be read, anything smaller means scale or geometry is wrong
+pub const MIN_PART_SIZE: [u32; 2] = [16, 8];
 
-    // top left corner
-    let x = (width / 2.0) - (reward_width / 2.0);
-    let y = reward_y + reward_height - reward_line;
+fn check_part_size(width: u32, height: u32) -> crate::Result<()> {
+    let [min_width, min_height] = MIN_PART_SIZE;
 
-    let partial_screenshot =
-        image.crop_imm(x as u32, y as u32, reward_width as u32, reward_line as u32);
+    if width < min_width || height < min_height {
+        return Err(crate::Error::PartTooSmall(width, height));
+    }
 
-    // workaround for now
-    let partial_screenshot = partial_screenshot.resize(
-        PIXEL_REWARD_WIDTH as u32,
-        PIXEL_REWARD_LINE_HEIGHT as u32,
-        image::imageops::Lanczos3,
-    );
+    Ok(())
+}
+
+/// returns None if the theme filter found no foreground, see [filter_and_separate_parts_from_part_box]
+///
+/// the filtered image and parts are written to `debug_dir` if it's set, see [DebugImages]
+pub fn extract_parts(
+    image: &DynamicImage,
+    theme: &Theme,
+    scale: f32,
+    layout: &RewardLayout,
+    debug_dir: Option<&Path>,
+) -> crate::Result<Option<Vec<DynamicImage>>> {
+    // image.save("input.png").unwrap();
+    let partial_screenshot = name_lines(image, scale, layout)?;
 
     // partial_screenshot
     //     .save("test.png")
@@ -36,40 +49,201 @@ pub fn extract_parts(image: &DynamicImage, theme: &Theme, scale: f32) -> Vec<Dyn
 
     // let line_height = (PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling) as usize;
 
-    filter_and_separate_parts_from_part_box(partial_screenshot, theme)
+    let parts =
+        filter_and_separate_parts_from_part_box(partial_screenshot, theme, scale, debug_dir);
+
+    Ok(parts)
+}
+
+/// The name lines at the bottom of the reward boxes, resized to 1080p
+fn name_lines(
+    image: &DynamicImage,
+    scale: f32,
+    layout: &RewardLayout,
+) -> crate::Result<DynamicImage> {
+    let reward_line = layout.line_height * scale;
+
+    let [x, reward_y, reward_width, reward_height] =
+        layout.area(image.width(), image.height(), scale);
+
+    // top left corner of the name lines, at the bottom of the reward boxes
+    let y = (reward_y + reward_height - reward_line).max(0.0);
+
+    let name_lines = image.crop_imm(x as u32, y as u32, reward_width as u32, reward_line as u32);
+
+    check_part_size(name_lines.width() / 4, name_lines.height())?;
+
+    // workaround for now, also undoes the UI scale so parts are always the same size
+    Ok(name_lines.resize(
+        PIXEL_REWARD_WIDTH as u32,
+        PIXEL_REWARD_LINE_HEIGHT as u32,
+        image::imageops::Lanczos3,
+    ))
+}
+
+/// Reward boxes sit on a dark band, brighter reward areas aren't a reward screen
+const REWARD_SCREEN_MAX_BRIGHTNESS: f32 = 0.5;
+
+/// Share of the reward area that has to be bright, reward names are bright text on the band
+const REWARD_SCREEN_MIN_BRIGHT_SHARE: f32 = 0.001;
+
+/// Pixels at least this bright count as text
+const REWARD_SCREEN_BRIGHT_PIXEL: u8 = 128;
+
+/// Scales outside of this are from a wrong geometry rather than a real screen
+const REWARD_SCREEN_SCALE: RangeInclusive<f32> = 0.25..=8.0;
+
+/// How much more of the reward area the best theme has to match than the next best
+const REWARD_SCREEN_MIN_THEME_MARGIN: f32 = 0.05;
+
+/// Share of the name lines that passes the theme filter on a reward screen,
+/// less is an empty screen, more is a bright area in the theme's colors rather than text
+const REWARD_SCREEN_FOREGROUND: RangeInclusive<f32> = 0.002..=0.3;
+
+/// Check for whether `image` looks like a reward screen, run before OCR
+///
+/// the scale has to be plausible, the reward area has to be a dark band with some bright text,
+/// one of `themes` has to clearly match it and its filter has to find about as much foreground
+/// in the name lines as reward names leave
+///
+/// false positives happen on other dark screens with text in the theme's colors in the same
+/// spot, so this only catches activations on obviously different screens
+pub fn is_reward_screen(
+    image: &DynamicImage,
+    scale: f32,
+    layout: &RewardLayout,
+    themes: &Themes,
+) -> bool {
+    if !REWARD_SCREEN_SCALE.contains(&scale) {
+        debug!("Scale {scale} is not a reward screen");
+        return false;
+    }
+
+    let [x, y, width, height] = layout.area(image.width(), image.height(), scale);
+    let area = image
+        .crop_imm(x as u32, y as u32, width as u32, height as u32)
+        .into_luma8();
+
+    let total = (area.width() * area.height()) as f32;
+
+    if total == 0.0 {
+        return false;
+    }
+
+    let brightness = area.pixels().map(|p| p[0] as f32).sum::<f32>() / total / 255.0;
+    let bright_share = area
+        .pixels()
+        .filter(|p| p[0] >= REWARD_SCREEN_BRIGHT_PIXEL)
+        .count() as f32
+        / total;
+
+    debug!("Reward area brightness {brightness:.3}, bright share {bright_share:.4}");
+
+    if brightness > REWARD_SCREEN_MAX_BRIGHTNESS || bright_share < REWARD_SCREEN_MIN_BRIGHT_SHARE {
+        return false;
+    }
+
+    let scores = themes.detect_theme_scores(image, scale, layout);
+    let Some(&(theme, best)) = scores.first() else {
+        return false;
+    };
+    let next = scores.get(1).map_or(0.0, |(_, score)| *score);
+
+    debug!("Reward theme {} by {:.3}", theme.name, best - next);
+
+    if best - next < REWARD_SCREEN_MIN_THEME_MARGIN {
+        return false;
+    }
+
+    let Ok(name_lines) = name_lines(image, scale, layout) else {
+        return false;
+    };
+
+    let (filtered, _) = theme.filter_scaled(name_lines, scale);
+    let foreground = filtered
+        .pixels()
+        .filter(|pixel| **pixel == FILTER_FOREGROUND)
+        .count() as f32
+        / (filtered.width() * filtered.height()) as f32;
+
+    debug!("Name line foreground {foreground:.4}");
+
+    REWARD_SCREEN_FOREGROUND.contains(&foreground)
+}
+
+/// Share of the foreground that has to be inside the reward boxes for a reward count to fit
+const REWARD_COUNT_COVERAGE: f32 = 0.9;
+
+/// Every reward box needs at least this share of the average box's foreground
+const REWARD_BOX_MIN_SHARE: f32 = 0.1;
+
+/// Left edge of the first reward box, rewards are centered so fewer rewards leave the sides empty
+fn reward_offset(count: u32, box_width: u32) -> u32 {
+    (4 - count) * box_width / 2
+}
+
+/// Number of rewards from 1 to 4, the smallest count whose boxes hold the foreground
+/// without any of them being empty
+pub fn detect_reward_count(filtered: &RgbImage) -> u32 {
+    // capped so a few noisy columns don't outweigh text
+    let density = (0..filtered.width())
+        .map(|x| {
+            let count = (0..filtered.height())
+                .filter(|y| *filtered.get_pixel(x, *y) == FILTER_FOREGROUND)
+                .count() as u32;
+
+            count.min(filtered.height() / 3)
+        })
+        .collect::<Vec<_>>();
+
+    let total = density.iter().sum::<u32>() as f32;
+    let box_width = filtered.width() / 4;
 
-    // vec![]
+    (1..=4)
+        .find(|count| {
+            let offset = reward_offset(*count, box_width);
+            let boxes = (0..*count)
+                .map(|i| {
+                    let start = (offset + i * box_width) as usize;
+                    let end = start + box_width as usize;
+                    density[start..end].iter().sum::<u32>() as f32
+                })
+                .collect::<Vec<_>>();
+
+            let inside = boxes.iter().sum::<f32>();
+            let average = inside / *count as f32;
+
+            debug!("[Rewards: {count}] boxes: {boxes:?}, total: {total}");
+
+            inside >= total * REWARD_COUNT_COVERAGE
+                && boxes.iter().all(|b| *b >= average * REWARD_BOX_MIN_SHARE)
+        })
+        .unwrap_or(4)
 }
 
+/// returns None if nothing passed the theme filter,
+/// which usually means the thresholds don't fit the screen rather than no rewards being shown
+///
+/// `scale` is what `image` was resized to 1080p from, see [Theme::filter_scaled]
 pub fn filter_and_separate_parts_from_part_box(
     image: DynamicImage,
     theme: &Theme,
-) -> Vec<DynamicImage> {
-    let (filtered, (total_even, total_odd)) = theme.filter(image);
+    scale: f32,
+    debug_dir: Option<&Path>,
+) -> Option<Vec<DynamicImage>> {
+    let (filtered, _) = theme.filter_scaled(image, scale);
 
-    #[cfg(debug_assertions)]
-    filtered
-        .save("test-images/other/filtered.png")
-        .expect("Failed to write filtered image");
+    crate::debug::save_image(debug_dir, "filtered.png", &filtered);
 
-    if total_even == 0.0 && total_odd == 0.0 {
-        return vec![];
+    if !filtered.pixels().any(|pixel| *pixel == FILTER_FOREGROUND) {
+        return None;
     }
 
-    let _total = total_even + total_odd;
-    // debug!("Even: {}", total_even / total);
-    // debug!("Odd: {}", total_odd / total);
-
     let box_width = filtered.width() / 4;
     let box_height = filtered.height();
 
-    let mut curr_left = 0;
-    let mut player_count = 4;
-
-    if total_odd > total_even {
-        curr_left = box_width / 2;
-        player_count = 3;
-    }
+    let player_count = detect_reward_count(&filtered);
+    let curr_left = reward_offset(player_count, box_width);
 
     let mut images = Vec::new();
 
@@ -99,15 +273,12 @@ pub fn filter_and_separate_parts_from_part_box(
             }
         }
 
-        #[cfg(debug_assertions)]
-        cropped
-            .save(format!("part-{}.png", i))
-            .expect("Failed to write image");
+        crate::debug::save_image(debug_dir, &format!("part-{i}.png"), &cropped);
 
         images.push(cropped.into());
     }
 
-    images
+    Some(images)
 }
 
 #[allow(unused)]
@@ -115,69 +286,448 @@ pub fn normalize_string(string: &str) -> String {
     string.replace(|c: char| !c.is_ascii_alphabetic(), "")
 }
 
+/// Common Latin OCR confusions, none of these show up in item names
+pub const DEFAULT_SUBSTITUTIONS: [(&str, &str); 6] = [
+    ("0", "O"),
+    ("1", "l"),
+    ("|", "l"),
+    ("5", "S"),
+    ("8", "B"),
+    ("vv", "w"),
+];
+
+/// Substrings OCR is known to confuse, replaced in OCR output before matching items
+///
+/// loaded as a list of `["from", "to"]` pairs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Substitutions(pub Vec<(String, String)>);
+
+impl Default for Substitutions {
+    fn default() -> Self {
+        Self(
+            DEFAULT_SUBSTITUTIONS
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl Substitutions {
+    /// `custom` substitutions followed by the defaults, so custom ones are applied first
+    pub fn with_defaults(custom: Substitutions) -> Self {
+        let mut substitutions = custom;
+        substitutions.0.extend(Self::default().0);
+        substitutions
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.0
+            .iter()
+            .filter(|(from, _)| !from.is_empty())
+            .fold(text.to_string(), |text, (from, to)| text.replace(from, to))
+    }
+}
+
+/// Tesseract page segmentation mode used on each reward part
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum OcrMode {
+    /// Names can wrap to 2 lines, so a block fits better than a single line
+    #[default]
+    SingleBlock,
+    SingleLine,
+    SingleWord,
+    SparseText,
+    Auto,
+}
+
+impl OcrMode {
+    pub fn page_seg_mode(self) -> PageSegMode {
+        match self {
+            OcrMode::SingleBlock => PageSegMode::PsmSingleBlock,
+            OcrMode::SingleLine => PageSegMode::PsmSingleLine,
+            OcrMode::SingleWord => PageSegMode::PsmSingleWord,
+            OcrMode::SparseText => PageSegMode::PsmSparseText,
+            OcrMode::Auto => PageSegMode::PsmAuto,
+        }
+    }
+
+    /// Mode to retry with when the result is empty or doesn't match an item
+    pub fn fallback(self) -> OcrMode {
+        match self {
+            OcrMode::Auto => OcrMode::SingleBlock,
+            _ => OcrMode::Auto,
+        }
+    }
+}
+
+/// Tesseract engine reused across parts, initializing it takes longer than reading a part
+#[derive(Default)]
+pub struct Ocr {
+    tesseract: Option<Tesseract>,
+}
+
+impl Ocr {
+    pub fn new() -> crate::Result<Self> {
+        Ok(Self {
+            tesseract: Some(Tesseract::new(None, Some("eng"))?),
+        })
+    }
+
+    pub fn image_to_string(
+        &mut self,
+        image: &DynamicImage,
+        mode: OcrMode,
+    ) -> crate::Result<String> {
+        self.image_to_string_with_confidence(image, mode)
+            .map(|(text, _)| text)
+    }
+
+    /// Text and Tesseract's mean word confidence from 0 to 100
+    pub fn image_to_string_with_confidence(
+        &mut self,
+        image: &DynamicImage,
+        mode: OcrMode,
+    ) -> crate::Result<(String, f32)> {
+        check_part_size(image.width(), image.height())?;
+
+        let buffer = image
+            .as_flat_samples_u8()
+            .ok_or(crate::Error::InvalidImageFormat)?;
+
+        // set_frame consumes the engine, if it fails a new one is made next time
+        let mut ocr = match self.tesseract.take() {
+            Some(ocr) => ocr,
+            None => Tesseract::new(None, Some("eng"))?,
+        };
+
+        ocr.set_page_seg_mode(mode.page_seg_mode());
+
+        let mut ocr = ocr.set_frame(
+            buffer.samples,
+            image.width() as i32,
+            image.height() as i32,
+            3,
+            3 * image.width() as i32,
+        )?;
+
+        let text = ocr.get_text();
+        let confidence = ocr.mean_text_conf() as f32;
+        self.tesseract = Some(ocr);
+
+        let result = text? //
+            .replace("\n", " ")
+            .trim()
+            .to_string();
+
+        Ok((result, confidence))
+    }
+}
+
 pub fn image_to_string(image: &DynamicImage) -> crate::Result<String> {
-    let mut ocr = Tesseract::new(None, Some("eng"))?;
-
-    let buffer = image
-        .as_flat_samples_u8()
-        .ok_or(crate::Error::InvalidImageFormat)?;
-
-    ocr = ocr.set_frame(
-        buffer.samples,
-        image.width() as i32,
-        image.height() as i32,
-        3,
-        3 * image.width() as i32,
-    )?;
+    image_to_string_with_mode(image, OcrMode::default())
+}
 
-    let result = ocr
-        .get_text()? //
-        .replace("\n", " ")
-        .trim()
-        .to_string();
+pub fn image_to_string_with_mode(image: &DynamicImage, mode: OcrMode) -> crate::Result<String> {
+    Ocr::default().image_to_string(image, mode)
+}
 
-    Ok(result)
+pub fn image_to_string_with_confidence(image: &DynamicImage) -> crate::Result<(String, f32)> {
+    Ocr::default().image_to_string_with_confidence(image, OcrMode::default())
 }
 
-pub fn reward_image_to_reward_names<'a>(
+/// `debug_images` is where the images of each step are written, nothing is written if it's none
+pub fn reward_image_to_parts<'a>(
     image: DynamicImage,
-    themes: Option<&'a Themes>,
-    theme: Option<&'a Theme>,
-) -> crate::Result<(Vec<String>, &'a Theme)> {
-    let themes = themes.unwrap_or(&DEFAULT_THEMES);
-    let scale = get_scale(&image)?;
+    selection: ThemeSelection<'a>,
+    layout: &RewardLayout,
+    debug_images: Option<&DebugImages>,
+    timings: &mut Timings,
+) -> crate::Result<(Option<Vec<DynamicImage>>, Cow<'a, Theme>)> {
+    let debug_dir = debug_images.map(DebugImages::begin_scan);
 
-    let theme = theme
-        .or_else(|| themes.detect_theme(&image, scale))
-        .ok_or(crate::Error::UnknownTheme)?;
+    let start = Instant::now();
+    let scale = layout.scale(&image)?;
+    let theme = selection.select(&image, scale, layout)?;
 
-    let parts = extract_parts(&image, theme, scale);
+    let start = timings.record("scale/theme detect", start);
+    let parts = extract_parts(&image, &theme, scale, layout, debug_dir.as_deref())?;
+    timings.record("part extraction", start);
 
     debug!("Extracted part images");
 
+    Ok((parts, theme))
+}
+
+/// Reward names with their OCR confidence from 0 to 100
+pub type RewardNames = Vec<(String, f32)>;
+
+pub fn reward_image_to_reward_names<'a>(
+    image: DynamicImage,
+    selection: ThemeSelection<'a>,
+    layout: &RewardLayout,
+    debug_images: Option<&DebugImages>,
+) -> crate::Result<(RewardNames, Cow<'a, Theme>)> {
+    let (parts, theme) = reward_image_to_parts(
+        image,
+        selection,
+        layout,
+        debug_images,
+        &mut Timings::default(),
+    )?;
+
+    // Tesseract isn't Sync, so every rayon thread gets its own engine,
+    // collect keeps the order of the parts
     let text = parts
-        .iter()
-        .map(image_to_string)
+        .unwrap_or_default()
+        .par_iter()
+        .map_init(Ocr::default, |ocr, part| {
+            ocr.image_to_string_with_confidence(part, OcrMode::default())
+        })
         .collect::<Result<_, _>>()?;
 
     Ok((text, theme))
 }
 
+/// Item read from each reward part, None where a part didn't match any item
+pub type PartItems = Vec<Option<Item>>;
+
+/// Like [reward_image_to_items] for raw pixels from a capture library,
+/// `bytes` are RGBA or RGB rows without padding
+///
+/// fails with [crate::Error::InvalidImageFormat] if the length isn't `width * height`
+/// times 4 or 3 channels
+#[allow(clippy::too_many_arguments)]
+pub fn reward_bytes_to_items<'a>(
+    items: &Items,
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    selection: ThemeSelection<'a>,
+    layout: &RewardLayout,
+    mode: OcrMode,
+    substitutions: &Substitutions,
+    debug_images: Option<&DebugImages>,
+    timings: &mut Timings,
+) -> crate::Result<(Option<PartItems>, Cow<'a, Theme>)> {
+    let pixels = width as usize * height as usize;
+
+    let image = match bytes.len() {
+        len if len == pixels * 4 => {
+            RgbaImage::from_raw(width, height, bytes.to_vec()).map(DynamicImage::ImageRgba8)
+        }
+        len if len == pixels * 3 => {
+            RgbImage::from_raw(width, height, bytes.to_vec()).map(DynamicImage::ImageRgb8)
+        }
+        _ => None,
+    }
+    .ok_or(crate::Error::InvalidImageFormat)?;
+
+    reward_image_to_items(
+        items,
+        image,
+        selection,
+        layout,
+        mode,
+        substitutions,
+        debug_images,
+        timings,
+    )
+}
+
+/// returns None if the theme filter found no foreground,
+/// parts that didn't match an item are None so the others are still shown
+///
+/// see [reward_image_to_parts] for `debug_images`
+#[allow(clippy::too_many_arguments)]
 pub fn reward_image_to_items<'a>(
     items: &Items,
     image: DynamicImage,
-    theme: Option<&'a Theme>,
-) -> crate::Result<(Vec<Item>, &'a Theme)> {
-    let (text, theme) = reward_image_to_reward_names(image, None, theme)?;
+    selection: ThemeSelection<'a>,
+    layout: &RewardLayout,
+    mode: OcrMode,
+    substitutions: &Substitutions,
+    debug_images: Option<&DebugImages>,
+    timings: &mut Timings,
+) -> crate::Result<(Option<PartItems>, Cow<'a, Theme>)> {
+    let (parts, theme) = reward_image_to_parts(image, selection, layout, debug_images, timings)?;
+
+    let Some(parts) = parts else {
+        return Ok((None, theme));
+    };
+
+    // like reward_image_to_reward_names every rayon thread gets its own engine,
+    // which is made on its first part
+    let parts = parts
+        .par_iter()
+        .enumerate()
+        .map_init(Ocr::default, |ocr, (i, part)| {
+            read_part(ocr, items, i, part, mode, substitutions)
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let mut ocr_time = Duration::ZERO;
+    let mut matching_time = Duration::ZERO;
 
     let mut result = vec![];
-    for item_og in text {
-        let Some(item) = items.find_item(&item_og) else {
-            return Ok((vec![], theme));
-        };
+    for (i, (item, part_ocr_time, part_matching_time)) in parts.into_iter().enumerate() {
+        timings.add(format!("ocr part {i}"), part_ocr_time);
+        ocr_time += part_ocr_time;
+        matching_time += part_matching_time;
 
         result.push(item);
     }
 
-    Ok((result, theme))
+    // summed over the parts, so more than the wall time when they're read in parallel
+    timings.add("ocr", ocr_time);
+    timings.add("item matching", matching_time);
+
+    Ok((Some(result), theme))
+}
+
+/// Item read from one reward part, retrying with [OcrMode::fallback] if it didn't match,
+/// with the time spent on OCR and on matching
+fn read_part(
+    ocr: &mut Ocr,
+    items: &Items,
+    i: usize,
+    part: &DynamicImage,
+    mode: OcrMode,
+    substitutions: &Substitutions,
+) -> crate::Result<(Option<Item>, Duration, Duration)> {
+    let start = Instant::now();
+    let (item_og, confidence) = ocr.image_to_string_with_confidence(part, mode)?;
+    let item_og = substitutions.apply(&item_og);
+    let mut ocr_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut item = items.find_item_with_confidence(&item_og, Some(confidence));
+    let mut matching_time = start.elapsed();
+
+    if item.is_none() {
+        let fallback = mode.fallback();
+        debug!("No match for {item_og:?} with {mode:?}, retrying with {fallback:?}");
+
+        let start = Instant::now();
+        let (item_og, confidence) = ocr.image_to_string_with_confidence(part, fallback)?;
+        let item_og = substitutions.apply(&item_og);
+        ocr_time += start.elapsed();
+
+        let start = Instant::now();
+        item = items.find_item_with_confidence(&item_og, Some(confidence));
+        matching_time += start.elapsed();
+    }
+
+    if item.is_none() {
+        debug!("[Part: {i}] didn't match any item");
+    }
+
+    Ok((item, ocr_time, matching_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+    use crate::theme::{DEFAULT_THEMES, DEFAULT_THEMES_SLICE};
+
+    #[test]
+    fn narrow_images_are_errors() {
+        let theme = &DEFAULT_THEMES_SLICE[0];
+
+        for (width, height) in [(40, 40), (10, 2000), (300, 1), (1, 1)] {
+            let image =
+                DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 180, 120])));
+            let layout = RewardLayout::default();
+            let scale = layout.scale(&image).unwrap();
+
+            // sampling outside the image used to panic here
+            DEFAULT_THEMES.detect_theme_scores(&image, scale, &layout);
+
+            let result = reward_image_to_parts(
+                image,
+                ThemeSelection::Fixed(theme),
+                &layout,
+                None,
+                &mut Timings::default(),
+            );
+
+            assert!(
+                matches!(result, Err(crate::Error::PartTooSmall(..))),
+                "{width}x{height}"
+            );
+        }
+    }
+
+    /// Filtered name line with a name in the middle of each of `count` centered reward boxes
+    fn filtered_rewards(count: u32) -> RgbImage {
+        let (width, height) = (PIXEL_REWARD_WIDTH as u32, PIXEL_REWARD_LINE_HEIGHT as u32);
+        let box_width = width / 4;
+        let offset = (4 - count) * box_width / 2;
+
+        RgbImage::from_fn(width, height, |x, y| {
+            let in_box = (x - offset.min(x)) % box_width;
+            let text = x >= offset
+                && x < offset + count * box_width
+                && (box_width / 4..box_width * 3 / 4).contains(&in_box)
+                && (height / 4..height * 3 / 4).contains(&y);
+
+            if text {
+                FILTER_FOREGROUND
+            } else {
+                FILTER_BACKGROUND
+            }
+        })
+    }
+
+    #[test]
+    fn reward_count() {
+        for count in 1..=4 {
+            assert_eq!(detect_reward_count(&filtered_rewards(count)), count);
+        }
+    }
+
+    /// 1080p screen of `background` with the reward area filled with `band`,
+    /// with bright names along the bottom if `names`
+    /// 1080p screen with the reward area filled with `band` and white stripes for reward names,
+    /// or a white bar over the whole name lines like a bright window
+    fn screen(background: u8, band: u8, names: bool, bar: bool) -> DynamicImage {
+        let layout = RewardLayout::default();
+        let [x, y, width, height] = layout.area(1920, 1080, 1.0);
+        let name_line = y + height - layout.line_height;
+
+        DynamicImage::ImageRgb8(RgbImage::from_fn(1920, 1080, |px, py| {
+            let (px, py) = (px as f32, py as f32);
+            let in_area = (x..x + width).contains(&px) && (y..y + height).contains(&py);
+            let in_name =
+                names && py >= name_line + 16.0 && py < name_line + 32.0 && px % 8.0 < 4.0;
+            let in_bar = bar && py >= name_line;
+
+            match (in_area, in_name || in_bar) {
+                (true, true) => Rgb([255; 3]),
+                (true, false) => Rgb([band; 3]),
+                (false, _) => Rgb([background; 3]),
+            }
+        }))
+    }
+
+    #[test]
+    fn reward_screen() {
+        let layout = RewardLayout::default();
+        let is_reward_screen =
+            |image, scale| is_reward_screen(&image, scale, &layout, &DEFAULT_THEMES);
+
+        assert!(is_reward_screen(screen(120, 20, true, false), 1.0));
+        // bright menus, dark screens without names, like loading screens
+        assert!(!is_reward_screen(screen(220, 220, true, false), 1.0));
+        assert!(!is_reward_screen(screen(120, 20, false, false), 1.0));
+        assert!(!is_reward_screen(screen(0, 0, false, false), 1.0));
+        // dark enough with bright pixels, but far more foreground than names leave
+        assert!(!is_reward_screen(screen(120, 20, false, true), 1.0));
+        // from a wrong geometry
+        assert!(!is_reward_screen(screen(120, 20, true, false), 20.0));
+    }
 }
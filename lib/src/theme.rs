@@ -1,35 +1,42 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
 use std::ops::Deref;
+use std::path::Path;
 use std::str::FromStr;
 
 use image::{DynamicImage, GenericImageView, Pixel, Rgb, RgbImage};
 use log::debug;
-use palette::{FromColor, Hsl, IntoColor, RgbHue, Srgb};
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use palette::color_difference::{Ciede2000, DeltaE};
+use palette::{FromColor, Hsl, IntoColor, Lab, RgbHue, Srgb};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::util::{
-    FILTER_BACKGROUND, FILTER_FOREGROUND, PIXEL_REWARD_LINE_HEIGHT, PIXEL_REWARD_WIDTH
-};
+use crate::util::{FILTER_BACKGROUND, FILTER_FOREGROUND, RewardLayout};
+
+/// Parses a `#rrggbb` or `#rgb` color, the `#` is optional
+pub fn parse_hex_color(hex: &str) -> crate::Result<Hsl> {
+    Srgb::from_str(hex)
+        .map_err(|_| crate::Error::InvalidHexColor(hex.to_string()))
+        .map(Srgb::<u8>::into_format)
+        .map(Srgb::<f32>::into_color)
+}
 
 fn deserialize_hex_str<'de, D: serde::de::Deserializer<'de>>(
     deserializer: D,
 ) -> Result<Hsl, D::Error> {
     let hex = <&str>::deserialize(deserializer)?;
 
-    Srgb::from_str(hex)
-        .map_err(serde::de::Error::custom)
-        .map(Srgb::<u8>::into_format)
-        .map(Srgb::<f32>::into_color)
+    parse_hex_color(hex).map_err(serde::de::Error::custom)
 }
 
 fn serialize_hex_str<S: serde::Serializer>(t: &Hsl, serializer: S) -> Result<S::Ok, S::Error> {
     let rgb: Srgb<f32> = t.into_format().into_color();
     let rgb: Srgb<u8> = rgb.into_format();
     let (r, g, b) = rgb.into_components();
-    let hex = format!("#{:x}{:x}{:x}", r, g, b);
+    let hex = format!("#{:02x}{:02x}{:02x}", r, g, b);
 
     serializer.serialize_str(&hex)
 }
@@ -353,10 +360,35 @@ pub const DEFAULT_THEMES_SLICE: &[Theme] = &[
     },
 ];
 
-pub const DEFAULT_THEMES: Themes = Themes(Cow::Borrowed(DEFAULT_THEMES_SLICE));
+pub const DEFAULT_THEMES: Themes = Themes(Cow::Borrowed(DEFAULT_THEMES_SLICE), ColorMetric::Rgb);
 
+/// Themes to detect from, and how pixel colors are compared to them, see [Themes::with_metric]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Themes(Cow<'static, [Theme]>);
+#[serde(transparent)]
+pub struct Themes(Cow<'static, [Theme]>, #[serde(skip)] ColorMetric);
+
+/// How far apart two colors are when detecting themes
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ColorMetric {
+    /// Sum of the RGB differences, fastest
+    #[default]
+    Rgb,
+    /// Euclidean distance in CIELAB, brightness is weighted like it's perceived
+    Lab,
+    /// CIEDE2000, most accurate for similar hues, slowest
+    Ciede2000,
+}
+
+impl ColorMetric {
+    pub fn difference(self, a: Hsl, b: Hsl) -> f32 {
+        match self {
+            Self::Rgb => color_difference((a, b)),
+            Self::Lab => Lab::from_color(a).delta_e(Lab::from_color(b)),
+            Self::Ciede2000 => Lab::from_color(a).difference(Lab::from_color(b)),
+        }
+    }
+}
 
 pub fn threshold_filter_custom(
     base: Hsl,
@@ -385,6 +417,40 @@ pub fn threshold_filter_custom(
     h.contains(&bh) && s.contains(&bs) && l.contains(&bl)
 }
 
+/// How much saturation and lightness thresholds widen per unit of scale away from 1080p
+pub const THRESHOLD_SCALE_WIDENING: f32 = 0.5;
+
+/// Widens saturation and lightness of `threshold` the further `scale` is from 1080p,
+/// resizing to 1080p blurs text into the background so fewer pixels match exactly
+///
+/// hue is left alone since blurring doesn't shift it much, 1080p is unchanged
+pub fn scale_threshold(threshold: [f32; 3], scale: f32) -> [f32; 3] {
+    let widen = 1.0 + (1.0 - scale).abs() * THRESHOLD_SCALE_WIDENING;
+    let [h, s, l] = threshold;
+
+    [h, s * widen, l * widen]
+}
+
+/// How far `color` is from `base` on each of hue, saturation and lightness,
+/// minus the matching threshold, so a value is out of range by how much it's over 0
+///
+/// explains why [threshold_filter_custom] accepts or rejects a color
+pub fn threshold_margins(base: Hsl, color: Rgb<u8>, threshold: [f32; 3]) -> [f32; 3] {
+    let rgb = Srgb::from_components((
+        color.0[0] as f32 / 255.0,
+        color.0[1] as f32 / 255.0,
+        color.0[2] as f32 / 255.0,
+    ));
+    let color = Hsl::from_color(rgb);
+    let [threshold_h, threshold_s, threshold_l] = threshold;
+
+    let h = (base.hue.into_positive_degrees() - color.hue.into_positive_degrees()).abs();
+    let s = (base.saturation - color.saturation).abs();
+    let l = (base.lightness - color.lightness).abs();
+
+    [h - threshold_h, s - threshold_s, l - threshold_l]
+}
+
 pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
     let rgb0 = Srgb::from_color(colors.0);
     let rgb1 = Srgb::from_color(colors.1);
@@ -392,7 +458,30 @@ pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
         * 255.0
 }
 
+impl Default for Themes {
+    fn default() -> Self {
+        DEFAULT_THEMES
+    }
+}
+
 impl Themes {
+    /// Loads a JSON list of themes in the same format as `assets/themes.json`
+    pub fn from_path(path: &Path) -> crate::Result<Themes> {
+        let themes: Vec<Theme> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if themes.is_empty() {
+            return Err(crate::Error::NoThemes);
+        }
+
+        Ok(Themes(Cow::Owned(themes), ColorMetric::default()))
+    }
+
+    /// Compares colors with `metric` when detecting themes
+    pub fn with_metric(mut self, metric: ColorMetric) -> Self {
+        self.1 = metric;
+        self
+    }
+
     pub fn by_name(&self, name: &str) -> Option<&Theme> {
         self.iter().find(|theme| theme.name == name)
     }
@@ -408,15 +497,105 @@ impl Themes {
 
         self.0
             .iter()
-            .map(|theme| (theme.clone(), color_difference((theme.primary, hsl))))
+            .map(|theme| (theme.clone(), self.1.difference(theme.primary, hsl)))
             .min_by(|a, b| a.1.total_cmp(&b.1))
             .unwrap()
     }
 
-    pub fn detect_theme(&self, image: &DynamicImage, scale: f32) -> Option<&Theme> {
+    pub fn detect_theme(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+    ) -> Option<&Theme> {
+        let (result, _) = self
+            .detect_theme_scores(image, scale, layout)
+            .into_iter()
+            .next()
+            .filter(|(_, score)| *score > 0.0)?;
+
+        debug!("Detected Theme: {:?}", result.name);
+
+        Some(result)
+    }
+
+    /// Share of the reward area each theme matches, best first and adding up to 1,
+    /// themes that match none of it score 0
+    ///
+    /// for showing how close detection was, like "Lotus 0.82, Corpus 0.11, ..."
+    pub fn detect_theme_scores(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+    ) -> Vec<(&Theme, f32)> {
+        let weights = self.theme_weights(image, scale, layout);
+        let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+
+        let unmatched = self
+            .iter()
+            .filter(|theme| {
+                !weights
+                    .iter()
+                    .any(|(matched, _)| matched.name == theme.name)
+            })
+            .map(|theme| (theme, 0.0));
+
+        weights
+            .iter()
+            .map(|(theme, weight)| (*theme, weight / total))
+            .chain(unmatched)
+            .collect()
+    }
+
+    /// How strongly each theme matches the reward area, best first, see [Themes::detect_theme]
+    pub fn theme_weights(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+    ) -> Vec<(&Theme, f32)> {
+        self.theme_weights_with_step(image, scale, layout, 1)
+    }
+
+    /// Like [Themes::detect_theme] but only samples every 8th pixel in both directions,
+    /// good enough to check a previously detected theme still fits
+    pub fn sample_theme(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+    ) -> Option<&Theme> {
+        self.detect_theme_with_step(image, scale, layout, 8)
+    }
+
+    fn detect_theme_with_step(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+        step: usize,
+    ) -> Option<&Theme> {
+        let (result, _) = self
+            .theme_weights_with_step(image, scale, layout, step)
+            .into_iter()
+            .next()?;
+
+        debug!("Detected Theme: {:?}", result.name);
+
+        Some(result)
+    }
+
+    fn theme_weights_with_step(
+        &self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+        step: usize,
+    ) -> Vec<(&Theme, f32)> {
         debug!("Detecting theme");
-        let line_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * scale;
-        let most_width = PIXEL_REWARD_WIDTH * scale;
+        let line_height = layout.line_height / 2.0 * scale;
+        let most_width = layout.width * scale;
 
         let min_width = most_width / 4.0;
 
@@ -424,18 +603,22 @@ impl Themes {
 
         let weights = (line_height as u32..image.height())
             .into_par_iter()
+            .step_by(step)
             .fold(
                 HashMap::new,
                 |mut weights: HashMap<Cow<'static, str>, f32>, y| {
                     let perc = (y as f32 - line_height) / (image.height() as f32 - line_height);
                     let total_width = min_width * perc + min_width;
 
-                    for x in 0..total_width as u32 {
-                        let closest = self.closest_from_color(
-                            image
-                                .get_pixel(x + (most_width - total_width) as u32 / 2, y)
-                                .to_rgb(),
-                        );
+                    let offset = (most_width - total_width) as u32 / 2;
+
+                    // most_width can be wider than the image on unusual aspect ratios
+                    for x in (0..total_width as u32)
+                        .step_by(step)
+                        .map(|x| x + offset)
+                        .take_while(|x| *x < image.width())
+                    {
+                        let closest = self.closest_from_color(image.get_pixel(x, y).to_rgb());
 
                         *weights.entry(closest.0.name).or_insert(0.0) +=
                             1.0 / (1.0 + closest.1).powi(4)
@@ -454,17 +637,36 @@ impl Themes {
 
         debug!("Weights: {:?}", weights);
 
-        let result = weights.iter().max_by(|a, b| a.1.total_cmp(b.1))?.0;
-
-        let result = self.iter().find(|theme| theme.name.eq(result))?;
+        let mut weights = self
+            .iter()
+            .filter_map(|theme| Some((theme, *weights.get(&theme.name)?)))
+            .collect::<Vec<_>>();
 
-        debug!("Detected Theme: {:?}", result.name);
+        weights.sort_by(|a, b| b.1.total_cmp(&a.1));
 
-        Some(result)
+        weights
     }
 }
 
 impl Theme {
+    /// Theme with the default thresholds, `secondary` is `primary` if unset
+    pub fn from_hex(
+        name: impl Into<Cow<'static, str>>,
+        primary: &str,
+        secondary: Option<&str>,
+    ) -> crate::Result<Theme> {
+        let primary = parse_hex_color(primary)?;
+        let secondary = secondary.map(parse_hex_color).transpose()?;
+
+        Ok(Theme {
+            name: name.into(),
+            primary,
+            secondary: secondary.unwrap_or(primary),
+            primary_threshold: default_threshold(),
+            secondary_threshold: default_threshold(),
+        })
+    }
+
     pub fn threshold_filter_custom(
         &self,
         color: Rgb<u8>,
@@ -482,7 +684,14 @@ impl Theme {
     }
 
     pub fn filter(&self, image: DynamicImage) -> (RgbImage, (f32, f32)) {
+        self.filter_scaled(image, 1.0)
+    }
+
+    /// Like [Theme::filter] for an image resized to 1080p from `scale`, see [scale_threshold]
+    pub fn filter_scaled(&self, image: DynamicImage, scale: f32) -> (RgbImage, (f32, f32)) {
         let mut filtered = image.into_rgb8();
+        let primary_threshold = scale_threshold(self.primary_threshold, scale);
+        let secondary_threshold = scale_threshold(self.secondary_threshold, scale);
 
         let mut _weight = 0.0;
         let mut total_even = 0.0;
@@ -493,9 +702,9 @@ impl Theme {
 
             for y in 0..filtered.height() {
                 let pixel = filtered.get_pixel_mut(x, y);
-                let [h, s, l] = self.primary_threshold;
+                let [h, s, l] = primary_threshold;
                 let primary_filter = threshold_filter_custom(self.primary, *pixel, h, s, l);
-                let [h, s, l] = self.secondary_threshold;
+                let [h, s, l] = secondary_threshold;
                 let secondary_filter = threshold_filter_custom(self.secondary, *pixel, h, s, l);
 
                 if primary_filter || secondary_filter {
@@ -562,3 +771,47 @@ pub fn auto_theme(name: impl ToString, image: &DynamicImage) -> crate::Result<Th
         secondary_threshold: [0.05, 0.05, 0.05],
     })
 }
+
+/// How the theme used to filter rewards is picked
+#[derive(Debug, Clone, Copy)]
+pub enum ThemeSelection<'a> {
+    /// Detects the closest theme from these
+    Auto(&'a Themes),
+    /// Always uses this theme
+    Fixed(&'a Theme),
+    /// Builds a theme from the profile line, see [auto_theme]
+    Calibrate,
+}
+
+impl Default for ThemeSelection<'_> {
+    fn default() -> Self {
+        ThemeSelection::Auto(&DEFAULT_THEMES)
+    }
+}
+
+impl<'a> From<Option<&'a Theme>> for ThemeSelection<'a> {
+    fn from(theme: Option<&'a Theme>) -> Self {
+        match theme {
+            Some(theme) => ThemeSelection::Fixed(theme),
+            None => ThemeSelection::default(),
+        }
+    }
+}
+
+impl<'a> ThemeSelection<'a> {
+    pub fn select(
+        self,
+        image: &DynamicImage,
+        scale: f32,
+        layout: &RewardLayout,
+    ) -> crate::Result<Cow<'a, Theme>> {
+        match self {
+            ThemeSelection::Auto(themes) => themes
+                .detect_theme(image, scale, layout)
+                .map(Cow::Borrowed)
+                .ok_or(crate::Error::UnknownTheme),
+            ThemeSelection::Fixed(theme) => Ok(Cow::Borrowed(theme)),
+            ThemeSelection::Calibrate => auto_theme("calibrated", image).map(Cow::Owned),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// Wall time of each stage of a scan, for finding where a slow activation spends its time
+#[derive(Default, Debug, Clone)]
+pub struct Timings {
+    stages: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    pub fn add(&mut self, stage: impl Into<String>, duration: Duration) {
+        self.stages.push((stage.into(), duration));
+    }
+
+    /// Records the time since `start` and returns a new checkpoint
+    pub fn record(&mut self, stage: impl Into<String>, start: Instant) -> Instant {
+        self.add(stage, start.elapsed());
+        Instant::now()
+    }
+
+    pub fn stages(&self) -> &[(String, Duration)] {
+        &self.stages
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .stages
+            .iter()
+            .map(|(stage, _)| stage.len())
+            .max()
+            .unwrap_or_default();
+
+        writeln!(f, "Timings:")?;
+
+        for (stage, duration) in &self.stages {
+            writeln!(
+                f,
+                "  {stage:<width$} {:>9.2}ms",
+                duration.as_secs_f64() * 1000.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use image::{DynamicImage, Rgb};
 
 pub const PIXEL_BASE_RESOLUTION: f32 = 1080.0;
+/// Width of a 16:9 screen at [PIXEL_BASE_RESOLUTION]
+pub const PIXEL_BASE_WIDTH: f32 = 1920.0;
 pub const PIXEL_REWARD_WIDTH: f32 = 960.0;
 pub const PIXEL_SINGLE_REWARD_WIDTH: f32 = PIXEL_REWARD_WIDTH / 4.0;
 pub const PIXEL_REWARD_HEIGHT: f32 = 240.0;
@@ -12,20 +14,129 @@ pub const PIXEL_PROFILE_LINE_Y: f32 = 85.0;
 
 pub const PIXEL_MARGIN_TOP: f32 = PIXEL_REWARD_Y + (PIXEL_REWARD_HEIGHT * 1.6666667);
 
+/// Where the rewards are on a 1080p screen, changes with warframe's UI scale setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardLayout {
+    /// Width of all 4 reward boxes
+    pub width: f32,
+    pub height: f32,
+    /// Top of the reward boxes
+    pub y: f32,
+    /// Height of the reward name lines at the bottom of the boxes
+    pub line_height: f32,
+    /// The image is already cropped to the 4 reward boxes, like a region screenshot,
+    /// so they aren't looked for in the middle of a full screen
+    pub pre_cropped: bool,
+}
+
+impl Default for RewardLayout {
+    fn default() -> Self {
+        Self {
+            width: PIXEL_REWARD_WIDTH,
+            height: PIXEL_REWARD_HEIGHT,
+            y: PIXEL_REWARD_Y,
+            line_height: PIXEL_REWARD_LINE_HEIGHT,
+            pre_cropped: false,
+        }
+    }
+}
+
+impl RewardLayout {
+    /// Layout for warframe's UI scale setting, 1.0 is the default layout
+    ///
+    /// the UI scales around the center of the screen, so the rewards move towards it
+    pub fn with_ui_scale(ui_scale: f32) -> Self {
+        let default = Self::default();
+        let center = PIXEL_BASE_RESOLUTION / 2.0;
+
+        Self {
+            width: default.width * ui_scale,
+            height: default.height * ui_scale,
+            y: center - (center - default.y) * ui_scale,
+            line_height: default.line_height * ui_scale,
+            ..default
+        }
+    }
+
+    /// Scale of `image` compared to 1080p, see [get_scale]
+    ///
+    /// pre-cropped images are scaled by their width against the reward boxes' width
+    pub fn scale(&self, image: &DynamicImage) -> crate::Result<f32> {
+        Ok(self.scale_for(image.width(), image.height()))
+    }
+
+    /// Like [RewardLayout::scale] for a `width`x`height` image that isn't loaded yet
+    pub fn scale_for(&self, width: u32, height: u32) -> f32 {
+        if self.pre_cropped {
+            width as f32 / self.width
+        } else if width >= height {
+            // height is the only thing that matters
+            height as f32 / PIXEL_BASE_RESOLUTION
+        } else {
+            width as f32 / PIXEL_BASE_WIDTH
+        }
+    }
+
+    /// `[x, y, width, height]` of the 4 reward boxes in a `width`x`height` image
+    ///
+    /// the UI is centered, so on ultrawide screens like 3440x1440 the extra width
+    /// is split evenly on both sides and the rewards stay in the middle of the image,
+    /// on portrait screens the 16:9 UI is centered vertically the same way
+    pub fn area(&self, width: u32, height: u32, scale: f32) -> [f32; 4] {
+        if self.pre_cropped {
+            return [0.0, 0.0, width as f32, height as f32];
+        }
+
+        let reward_width = self.width * scale;
+        let x = (width as f32 / 2.0) - (reward_width / 2.0);
+        let top = ((height as f32 - PIXEL_BASE_RESOLUTION * scale) / 2.0).max(0.0);
+
+        [x, top + self.y * scale, reward_width, self.height * scale]
+    }
+}
+
 pub const FILTER_BACKGROUND: Rgb<u8> = Rgb([255; 3]);
 pub const FILTER_FOREGROUND: Rgb<u8> = Rgb([0; 3]);
 
+/// UI scale of `image` compared to 1080p
+///
+/// landscape screens scale with height, portrait screens fit the 16:9 UI to their width
 pub fn get_scale(image: &DynamicImage) -> crate::Result<f32> {
     if image.width() >= image.height() {
         // height is the only thing that matters
         Ok(image.height() as f32 / PIXEL_BASE_RESOLUTION)
     } else {
-        Err(crate::Error::InvalidSize(image.width(), image.height()))
+        Ok(image.width() as f32 / PIXEL_BASE_WIDTH)
     }
+}
 
-    // if image.width() * 9 > image.height() * 16 {
-    //     image.height() as f32 / 1080.0
-    // } else {
-    //     image.width() as f32 / 1920.0
-    // }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: u32, height: u32) -> [f32; 4] {
+        let image = DynamicImage::new_rgb8(width, height);
+        let layout = RewardLayout::default();
+        let scale = layout.scale(&image).unwrap();
+
+        layout.area(width, height, scale).map(f32::round)
+    }
+
+    #[test]
+    fn reward_area_16_9() {
+        assert_eq!(area(1920, 1080), [480.0, 220.0, 960.0, 240.0]);
+        assert_eq!(area(2560, 1440), [640.0, 293.0, 1280.0, 320.0]);
+    }
+
+    #[test]
+    fn reward_area_ultrawide() {
+        assert_eq!(area(3440, 1440), [1080.0, 293.0, 1280.0, 320.0]);
+        assert_eq!(area(5120, 1440), [1920.0, 293.0, 1280.0, 320.0]);
+    }
+
+    #[test]
+    fn reward_area_portrait() {
+        assert_eq!(area(1080, 1920), [270.0, 780.0, 540.0, 135.0]);
+        assert_eq!(area(1440, 2560), [360.0, 1040.0, 720.0, 180.0]);
+    }
 }
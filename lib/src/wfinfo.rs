@@ -1,9 +1,11 @@
 #![allow(unused)]
 
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use log::warn;
 use palette::num::MinMax;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -19,10 +21,37 @@ pub struct PriceItem {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DucatItem {
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_ducats")]
     pub ducats: usize,
 }
 
+/// Ducats are usually a number but warframestat sometimes sends a string or nests them,
+/// anything that isn't a number is 0 instead of failing the whole item list
+fn deserialize_ducats<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    fn ducats(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::Number(number) => number
+                .as_u64()
+                .or_else(|| number.as_f64().map(|n| n.max(0.0) as u64))
+                .unwrap_or_default() as usize,
+            serde_json::Value::String(string) => string.trim().parse().unwrap_or_default(),
+            serde_json::Value::Object(object) => object
+                .get("ducats")
+                .or_else(|| object.get("value"))
+                .map(ducats)
+                .unwrap_or_default(),
+            _ => 0,
+        }
+    }
+
+    let value = serde_json::Value::deserialize(deserializer)?;
+
+    Ok(ducats(&value))
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum EquipmentType {
     Warframes,
@@ -44,8 +73,10 @@ pub struct EquipmentItem {
     pub parts: HashMap<String, DucatItem>,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
 pub enum Refinement {
+    #[default]
     Intact,
     Exceptional,
     Flawless,
@@ -63,6 +94,35 @@ pub struct Relic {
     pub common3: Option<String>,
 }
 
+impl Relic {
+    /// Rewards with the chance of getting each at `refinement`
+    pub fn rewards(&self, refinement: Refinement) -> impl Iterator<Item = (&str, f32)> {
+        let rare = [&self.rare1].map(|name| (name, refinement.rare_chance()));
+        let uncommon =
+            [&self.uncommon1, &self.uncommon2].map(|name| (name, refinement.uncommon_chance()));
+        let common = [&self.common1, &self.common2, &self.common3]
+            .map(|name| (name, refinement.common_chance()));
+
+        rare.into_iter()
+            .chain(uncommon)
+            .chain(common)
+            .filter_map(|(name, chance)| Some((name.as_deref()?, chance)))
+    }
+
+    /// Platinum a single opening is worth on average at `refinement`,
+    /// rewards without a price count as 0
+    ///
+    /// reward names go through [normalize_part_name] like item names, so typos still match
+    pub fn expected_platinum(&self, items: &Items, refinement: Refinement) -> f32 {
+        self.rewards(refinement)
+            .filter_map(|(name, chance)| {
+                let platinum = items.by_name(&normalize_part_name(name))?.platinum?;
+                Some(platinum.value() * chance)
+            })
+            .sum()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Relics {
     #[serde(rename = "Lith")]
@@ -75,6 +135,62 @@ pub struct Relics {
     pub axi: HashMap<String, Relic>,
 }
 
+impl Relics {
+    /// Every relic with its tier, like `("Axi", "A1", relic)`
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str, &Relic)> {
+        [
+            ("Lith", &self.lith),
+            ("Meso", &self.meso),
+            ("Neo", &self.neo),
+            ("Axi", &self.axi),
+        ]
+        .into_iter()
+        .flat_map(|(tier, relics)| {
+            relics
+                .iter()
+                .map(move |(name, relic)| (tier, name.as_str(), relic))
+        })
+    }
+
+    /// `owned` relics by [Relic::expected_platinum] at `refinement`, best first
+    ///
+    /// names are like "Axi A1", or just "A1" for every tier that has one, ignoring case,
+    /// names that aren't found are returned separately as they were given
+    pub fn rank_by_ev(
+        &self,
+        owned: &[&str],
+        items: &Items,
+        refinement: Refinement,
+    ) -> (Vec<(String, f32)>, Vec<String>) {
+        let mut ranked = vec![];
+        let mut missing = vec![];
+
+        for &original in owned {
+            let owned = original.trim().to_lowercase();
+            let len = ranked.len();
+
+            ranked.extend(
+                self.iter()
+                    .filter(|(tier, name, _)| {
+                        let name = name.to_lowercase();
+                        owned == name || owned == format!("{} {name}", tier.to_lowercase())
+                    })
+                    .map(|(tier, name, relic)| {
+                        let platinum = relic.expected_platinum(items, refinement);
+                        (format!("{tier} {name}"), platinum)
+                    }),
+            );
+
+            if ranked.len() == len {
+                missing.push(original.trim().to_string());
+            }
+        }
+
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        (ranked, missing)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FilteredItems {
     pub errors: Vec<String>,
@@ -112,6 +228,42 @@ impl Refinement {
     }
 }
 
+/// Format of a prices file, [PriceFormat::from_path] guesses it from the extension
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum PriceFormat {
+    /// https://api.warframestat.us/wfinfo/prices, fetched again when out of date
+    #[default]
+    Json,
+    /// `name,platinum,ducats` rows, read as is and never fetched, see [Items::from_csv]
+    Csv,
+    /// Like csv but separated by tabs
+    Tsv,
+}
+
+impl PriceFormat {
+    /// Json for anything that isn't `.csv` or `.tsv`
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_ref().and_then(|extension| extension.to_str()) {
+            Some("csv") => Self::Csv,
+            Some("tsv") => Self::Tsv,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn delimiter(self) -> char {
+        match self {
+            Self::Tsv => '\t',
+            Self::Json | Self::Csv => ',',
+        }
+    }
+}
+
 pub fn load_from_str<T: DeserializeOwned>(json: &str) -> crate::Result<T> {
     serde_json::from_str::<T>(json).map_err(Into::into)
 }
@@ -120,12 +272,54 @@ pub fn load_from_reader<T: DeserializeOwned>(json: impl Read) -> crate::Result<T
     serde_json::from_reader::<_, T>(json).map_err(Into::into)
 }
 
-#[derive(Debug, Clone)]
+/// Platinum price of an item, either a single average or a low/high spread
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Price {
+    Avg(f32),
+    Range { lo: f32, hi: f32 },
+}
+
+impl Price {
+    /// Single value for sorting and ratios, midpoint when it's a range
+    pub fn value(&self) -> f32 {
+        match self {
+            Price::Avg(avg) => *avg,
+            Price::Range { lo, hi } => (lo + hi) / 2.0,
+        }
+    }
+}
+
+impl From<f32> for Price {
+    fn from(avg: f32) -> Self {
+        Self::Avg(avg)
+    }
+}
+
+/// Whole platinum rounded down, `{:.1}` shows that many decimals instead
+impl std::fmt::Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self, f.precision()) {
+            (Price::Avg(avg), None) => write!(f, "{}", avg.floor() as u32),
+            (Price::Avg(avg), Some(p)) => write!(f, "{avg:.p$}"),
+            (Price::Range { lo, hi }, None) => {
+                write!(f, "{}–{}", lo.floor() as u32, hi.floor() as u32)
+            }
+            (Price::Range { lo, hi }, Some(p)) => write!(f, "{lo:.p$}–{hi:.p$}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
     tokens: Vec<String>,
     pub name: String,
-    pub platinum: Option<f32>,
+    pub platinum: Option<Price>,
+    /// Live price from warframe.market, only fetched when asked for
+    pub market_platinum: Option<Price>,
+    /// Price of the whole set this part belongs to
+    pub set_platinum: Option<Price>,
     pub ducats: Option<usize>,
+    /// From wfinfo's `ignored_items`, like Forma Blueprint or Kuva, see [Item::is_tradeable]
     pub ignored: bool,
     pub vaulted: bool,
 }
@@ -133,7 +327,7 @@ pub struct Item {
 impl Item {
     pub fn new(
         name: String,
-        platinum: Option<f32>,
+        platinum: Option<Price>,
         ducats: Option<usize>,
         ignored: bool,
         vaulted: bool,
@@ -142,28 +336,46 @@ impl Item {
             tokens: name.split_ascii_whitespace().map(str::to_owned).collect(),
             name,
             platinum,
+            market_platinum: None,
+            set_platinum: None,
             ducats,
             ignored,
             vaulted,
         }
     }
+
+    /// Ignored items can't be traded, so they never have a price
+    pub fn is_tradeable(&self) -> bool {
+        !self.ignored
+    }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Items {
     items: Vec<Item>,
     min_len: usize,
     max_len: usize,
+    /// Edit distance allowed per token, relative to its length, [DEFAULT_MATCH_THRESHOLD] if None
+    #[serde(skip)]
+    max_relative_distance: Option<f32>,
+}
+
+/// Bump whenever [Items] or [Item] change, so older databases get rebuilt
+pub const ITEMS_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+struct ItemsDatabase<T> {
+    version: u32,
+    /// Files the items were built from, see [Items::save]
+    #[serde(default)]
+    sources: Vec<PathBuf>,
+    items: T,
 }
 
 impl Items {
     pub fn new(price_items: PriceItems, filtered_items: FilteredItems) -> Self {
         if price_items.is_empty() {
-            return Self {
-                items: vec![],
-                min_len: 0,
-                max_len: 0,
-            };
+            return Self::default();
         }
 
         let mut items = vec![];
@@ -177,30 +389,93 @@ impl Items {
         items.extend(
             ignored_items
                 .into_keys()
-                .map(|name| Item::new(name, None, None, true, false)),
+                .map(|name| Item::new(normalize_part_name(&name), None, None, true, false)),
         );
 
-        let eqmt = eqmt
-            .into_iter()
-            .flat_map(|(_, e)| e.parts.into_iter().map(move |item| (e.vaulted, item)));
-
-        for (vaulted, (name, item)) in eqmt {
-            let platinum = price_items
-                .iter() //
-                .filter(|item| !item.name.ends_with("Set"))
-                .find(|item| {
-                    item.name
-                        // some reason there is a single typo in prices api
-                        // "Kompressa Prime Receiver" turns into "Kompressa Prime Reciever"
-                        .replace("Reciever", "Receiver")
-                        .starts_with(&name)
-                })
-                .map(|item| item.custom_avg);
-
-            let item = Item::new(name, platinum, Some(item.ducats), false, vaulted);
+        // "Ash Prime" -> price of "Ash Prime Set"
+        let set_prices = price_items
+            .iter()
+            .filter_map(|item| Some((item.name.strip_suffix(" Set")?, item.custom_avg)))
+            .collect::<HashMap<_, _>>();
+
+        let eqmt = eqmt.into_iter().flat_map(|(set, e)| {
+            let set_platinum = set_prices.get(set.as_str()).copied().map(Price::Avg);
+
+            e.parts
+                .into_iter()
+                .map(move |item| (e.vaulted, set_platinum, item))
+        });
+
+        // the prices api has typos, like "Kompressa Prime Reciever"
+        let part_prices = price_items
+            .iter()
+            .filter(|item| !item.name.ends_with("Set"))
+            .map(|item| (normalize_part_name(&item.name), item.custom_avg))
+            .collect::<Vec<_>>();
+
+        for (vaulted, set_platinum, (name, item)) in eqmt {
+            let name = normalize_part_name(&name);
+            let platinum = part_prices
+                .iter()
+                .find(|(price_name, _)| price_name.starts_with(&name))
+                .map(|(_, custom_avg)| Price::Avg(*custom_avg));
+
+            let mut item = Item::new(name, platinum, Some(item.ducats), false, vaulted);
+            item.set_platinum = set_platinum;
             items.push(item);
         }
 
+        Self::from_items(items)
+    }
+
+    /// Items from a price sheet with `name,platinum,ducats` rows split by `delimiter`,
+    /// like [PriceFormat::Csv] or [PriceFormat::Tsv]
+    ///
+    /// platinum and ducats can be left empty, a first row that isn't a price is a header
+    /// and skipped, so are empty lines
+    pub fn from_csv(reader: impl Read, delimiter: char) -> crate::Result<Self> {
+        let mut csv = String::new();
+        BufReader::new(reader).read_to_string(&mut csv)?;
+
+        let mut items = vec![];
+
+        for (i, row) in csv.lines().enumerate() {
+            if row.trim().is_empty() {
+                continue;
+            }
+
+            let mut columns = row.split(delimiter).map(str::trim);
+            let name = columns.next().filter(|name| !name.is_empty());
+            let platinum = columns.next().filter(|platinum| !platinum.is_empty());
+            let ducats = columns.next().filter(|ducats| !ducats.is_empty());
+
+            let platinum = platinum.map(str::parse::<f32>).transpose();
+            let ducats = ducats.map(str::parse::<usize>).transpose();
+
+            let (Some(name), Ok(platinum), Ok(ducats)) = (name, platinum, ducats) else {
+                if i == 0 {
+                    continue;
+                }
+
+                return Err(crate::Error::InvalidPriceRow {
+                    line: i + 1,
+                    row: row.to_string(),
+                });
+            };
+
+            items.push(Item::new(
+                normalize_part_name(name),
+                platinum.map(Price::Avg),
+                ducats,
+                false,
+                false,
+            ));
+        }
+
+        Ok(Self::from_items(items))
+    }
+
+    fn from_items(items: Vec<Item>) -> Self {
         let min_len = items.iter().map(|item| item.name.len()).min().unwrap_or(0);
         let max_len = items.iter().map(|item| item.name.len()).max().unwrap_or(0);
 
@@ -208,9 +483,57 @@ impl Items {
             items,
             min_len,
             max_len,
+            max_relative_distance: None,
         }
     }
 
+    /// How far OCR text can be from an item name and still match, relative to each token's length
+    ///
+    /// higher matches blurry captures, lower avoids wrong matches on noisy ones
+    pub fn with_max_relative_distance(mut self, max_relative_distance: Option<f32>) -> Self {
+        self.max_relative_distance = max_relative_distance;
+        self
+    }
+
+    /// Saves already built items, so they can be loaded without rebuilding them
+    ///
+    /// `sources` are the files the items were built from, [Items::load] only accepts the same ones
+    pub fn save(&self, path: impl AsRef<Path>, sources: &[&Path]) -> crate::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let database = ItemsDatabase {
+            version: ITEMS_VERSION,
+            sources: sources.iter().map(|source| source.to_path_buf()).collect(),
+            items: self,
+        };
+
+        serde_json::to_writer(BufWriter::new(file), &database)?;
+
+        Ok(())
+    }
+
+    /// Loads items saved with [Items::save],
+    /// fails if they were saved by a different version or built from other `sources`
+    pub fn load(path: impl AsRef<Path>, sources: &[&Path]) -> crate::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let database: ItemsDatabase<serde_json::Value> =
+            serde_json::from_reader(BufReader::new(file))?;
+
+        if database.version != ITEMS_VERSION {
+            return Err(crate::Error::ItemsVersionMismatch {
+                found: database.version,
+                expected: ITEMS_VERSION,
+            });
+        }
+
+        if !database.sources.iter().eq(sources) {
+            return Err(crate::Error::ItemsSourcesMismatch {
+                found: database.sources,
+            });
+        }
+
+        Ok(serde_json::from_value(database.items)?)
+    }
+
     pub const fn min_len(&self) -> usize {
         self.min_len
     }
@@ -219,80 +542,503 @@ impl Items {
         self.max_len
     }
 
+    /// True when there were no prices to build items from, see [Items::new]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
     pub fn find_item(&self, item_name: &str) -> Option<Item> {
-        let item_name = item_name.trim();
+        self.find_item_with_confidence(item_name, None)
+    }
+
+    /// Item with exactly this name, for names that come from wfinfo like relic rewards
+    pub fn by_name(&self, name: &str) -> Option<&Item> {
+        self.items.iter().find(|item| item.name == name)
+    }
+
+    /// Like [Items::find_item], `confidence` is the OCR confidence from 0 to 100
+    ///
+    /// low confidence means more characters were likely misread,
+    /// so tokens are allowed to be further from the item name
+    pub fn find_item_with_confidence(
+        &self,
+        item_name: &str,
+        confidence: Option<f32>,
+    ) -> Option<Item> {
+        self.find_candidates_with_confidence(item_name, confidence, 1)
+            .into_iter()
+            .next()
+            .map(|(item, _)| item)
+    }
+
+    /// Up to `n` items that could be `item_name`, best first, with their summed token distance
+    ///
+    /// useful to see why a reward was misidentified
+    pub fn find_candidates(&self, item_name: &str, n: usize) -> Vec<(Item, usize)> {
+        self.find_candidates_with_confidence(item_name, None, n)
+    }
+
+    /// Like [Items::find_candidates], see [Items::find_item_with_confidence] for `confidence`
+    ///
+    /// tokens are compared by position, an item is a candidate if every token both names have
+    /// is close enough, except [GENERIC_TOKENS] which only add to the distance,
+    /// missing or extra tokens count their whole length
+    ///
+    /// every item is scored before picking, so a single token name like "Forma" only matches
+    /// by its first token and still gets the lowest distance of all items, not the first
+    pub fn find_candidates_with_confidence(
+        &self,
+        item_name: &str,
+        confidence: Option<f32>,
+        n: usize,
+    ) -> Vec<(Item, usize)> {
+        let item_name = normalize_part_name(item_name);
+        let threshold = self
+            .max_relative_distance
+            .unwrap_or(DEFAULT_MATCH_THRESHOLD);
+        let threshold = match confidence {
+            Some(confidence) if confidence < LOW_CONFIDENCE => threshold * LOW_CONFIDENCE_FACTOR,
+            _ => threshold,
+        };
+        let max_distance = |len: usize| (len as f32 * threshold) as usize;
 
         if !(self.min_len..=self.max_len).contains(&item_name.len()) {
-            return None;
+            return vec![];
         }
 
-        let mut current_matches = self.items.clone();
+        let tokens = item_name.split_ascii_whitespace().collect::<Vec<_>>();
+
+        let mut candidates = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                let mut score = 0;
+                let mut any_match = false;
+                let mut all_match = true;
+
+                for i in 0..tokens.len().max(item.tokens.len()) {
+                    match (item.tokens.get(i), tokens.get(i)) {
+                        (Some(expected), Some(token)) => {
+                            let distance = levenshtein::levenshtein(expected, token);
+                            let matches = max_distance(expected.len()) >= distance;
+                            let generic = GENERIC_TOKENS.contains(&expected.as_str());
 
-        for (i, token) in item_name.split_ascii_whitespace().enumerate() {
-            let best_match = current_matches
+                            any_match |= matches;
+                            all_match &= matches || generic;
+                            score += distance;
+                        }
+                        (Some(missing), None) => score += missing.len(),
+                        (None, Some(extra)) => score += extra.len(),
+                        (None, None) => {}
+                    }
+                }
+
+                (any_match && all_match).then_some((item, score))
+            })
+            .collect::<Vec<_>>();
+
+        // stable, so ties keep the order of the items
+        candidates.sort_by_key(|(_, score)| *score);
+
+        candidates
+            .into_iter()
+            .take(n)
+            .map(|(item, score)| (item.clone(), score))
+            .collect()
+    }
+}
+
+/// Tokens shared by so many items that they don't tell them apart,
+/// "Ash Prime Chassis" is not "Ash Prime Systems" just because "Ash Prime" matches
+pub const GENERIC_TOKENS: &[&str] = &["Prime", "Blueprint"];
+
+/// Misspelled words and what they should be, from typos in wfinfo's data
+pub const PART_NAME_CORRECTIONS: &[(&str, &str)] = &[
+    ("Reciever", "Receiver"),
+    ("Barrell", "Barrel"),
+    ("Stitck", "Stock"),
+];
+
+/// Fixes words in [PART_NAME_CORRECTIONS], strips punctuation around words
+/// and collapses whitespace, used on both wfinfo names and OCR output so they compare the same
+///
+/// `" Kompressa  Prime Reciever."` becomes `"Kompressa Prime Receiver"`
+pub fn normalize_part_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            PART_NAME_CORRECTIONS
                 .iter()
-                .filter(|item| i < item.tokens.len())
-                .map(|item| (item, levenshtein::levenshtein(&item.tokens[i], token)))
-                // .inspect(|(item, score)| println!("{} {score}", item.tokens[i]))
-                .filter(|(item, score)| item.tokens[i].len() / 3 >= *score)
-                .min_by_key(|(_, score)| *score);
+                .find(|(typo, _)| *typo == word)
+                .map_or(word, |(_, correction)| correction)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-            let Some((best_match, score)) = best_match else {
-                continue;
-            };
+/// OCR confidence below this makes [Items::find_item_with_confidence] more lenient
+pub const LOW_CONFIDENCE: f32 = 60.0;
 
-            let best_matched_token = best_match.tokens[i].clone();
+/// Threshold is multiplied by this below [LOW_CONFIDENCE], 1/3 becomes 1/2 by default
+pub const LOW_CONFIDENCE_FACTOR: f32 = 1.5;
 
-            current_matches.retain(|item| {
-                item.tokens
-                    .get(i)
-                    .filter(|t| **t == best_matched_token)
-                    .is_some()
-            });
-        }
+/// A token matches if at most a third of its characters are different
+pub const DEFAULT_MATCH_THRESHOLD: f32 = 1.0 / 3.0;
+
+/// Delay before the first retry, doubled for every retry after it
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
-        current_matches.into_iter().next()
+/// How [WfInfo] fetches, a failed request is only returned once every retry failed too
+#[derive(Debug, Clone, Copy)]
+pub struct FetchSettings {
+    /// Retries after the first attempt, waiting [RETRY_DELAY] then twice as long each time
+    pub retries: u32,
+    /// For connecting and for the whole request
+    pub timeout: Duration,
+}
+
+impl Default for FetchSettings {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            timeout: Duration::from_secs(5),
+        }
     }
 }
 
 pub struct WfInfo {
     client: reqwest::Client,
+    retries: u32,
 }
 
 impl WfInfo {
     pub fn new() -> crate::Result<Self> {
+        Self::with_settings(FetchSettings::default())
+    }
+
+    pub fn with_settings(settings: FetchSettings) -> crate::Result<Self> {
         let client = reqwest::Client::builder()
-            .connect_timeout(Duration::from_secs(5))
-            .timeout(Duration::from_secs(5))
+            .connect_timeout(settings.timeout)
+            .timeout(settings.timeout)
             .build()?;
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            retries: settings.retries,
+        })
+    }
+
+    /// Retries timeouts, connection errors and server errors, anything else fails right away
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> crate::Result<T> {
+        let mut delay = RETRY_DELAY;
+        let mut retries = self.retries;
+
+        loop {
+            let result = async {
+                self.client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+            }
+            .await;
+
+            let err = match result {
+                Ok(t) => return Ok(t),
+                Err(err) => err,
+            };
+
+            let retryable = err.is_timeout()
+                || err.is_connect()
+                || err.status().is_some_and(|status| status.is_server_error());
+
+            if !retryable || retries == 0 {
+                return Err(err.into());
+            }
+
+            warn!("Failed to fetch {url}, retrying in {delay:?}: {err}");
+            tokio::time::sleep(delay).await;
+
+            delay *= 2;
+            retries -= 1;
+        }
     }
 
     pub async fn fetch(&self) -> crate::Result<(PriceItems, FilteredItems)> {
-        let prices = self.fetch_prices().await?;
-        let filtered = self.fetch_filtered_items().await?;
+        tokio::try_join!(self.fetch_prices(), self.fetch_filtered_items())
+    }
+
+    /// Fetches and builds [Items] in memory, nothing is read from or written to disk
+    ///
+    /// for callers with their own caching, `bin::cache::get_items` caches to files
+    pub async fn fetch_items(&self) -> crate::Result<Items> {
+        let (prices, filtered) = self.fetch().await?;
 
-        Ok((prices, filtered))
+        Ok(Items::new(prices, filtered))
     }
 
     pub async fn fetch_prices(&self) -> crate::Result<PriceItems> {
-        self.client
-            .get("https://api.warframestat.us/wfinfo/prices")
-            .send()
-            .await?
-            .json()
+        self.get_json("https://api.warframestat.us/wfinfo/prices")
             .await
-            .map_err(Into::into)
     }
 
     pub async fn fetch_filtered_items(&self) -> crate::Result<FilteredItems> {
-        self.client
-            .get("https://api.warframestat.us/wfinfo/filtered_items")
-            .send()
-            .await?
-            .json()
+        self.get_json("https://api.warframestat.us/wfinfo/filtered_items")
             .await
-            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(csv: &str) -> Items {
+        Items::from_csv(csv.as_bytes(), ',').unwrap()
+    }
+
+    const PARTS: &str = "Ash Prime Neuroptics,10
+Ash Prime Systems,15
+Braton Prime Barrel,5
+Kompressa Prime Receiver,20
+Forma Blueprint";
+
+    fn relic(rare: &str, uncommon: &str, common: &str) -> Relic {
+        Relic {
+            vaulted: false,
+            rare1: Some(rare.into()),
+            uncommon1: Some(uncommon.into()),
+            uncommon2: None,
+            common1: Some(common.into()),
+            common2: None,
+            common3: None,
+        }
+    }
+
+    #[test]
+    fn database_is_tied_to_its_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("items.json");
+        let prices = dir.path().join("prices.json");
+        let other_prices = dir.path().join("other_prices.json");
+        let filtered_items = dir.path().join("filtered_items.json");
+
+        items(PARTS)
+            .save(&path, &[&prices, &filtered_items])
+            .unwrap();
+
+        let loaded = Items::load(&path, &[&prices, &filtered_items]).unwrap();
+        assert!(loaded.find_item("Ash Prime Systems").is_some());
+
+        assert!(matches!(
+            Items::load(&path, &[&other_prices, &filtered_items]),
+            Err(crate::Error::ItemsSourcesMismatch { .. })
+        ));
+        assert!(Items::load(&path, &[&prices]).is_err());
+    }
+
+    #[test]
+    fn expected_platinum_by_refinement() {
+        let items = items("Kompressa Prime Receiver,100\nAsh Prime Neuroptics,10\nForma Blueprint");
+        // wfinfo's typo is normalized like the item names, unpriced rewards count as 0
+        let relic = relic(
+            "Kompressa Prime Reciever",
+            "Ash Prime Neuroptics",
+            "Forma Blueprint",
+        );
+
+        let intact = relic.expected_platinum(&items, Refinement::Intact);
+        let radiant = relic.expected_platinum(&items, Refinement::Radiant);
+
+        assert!((intact - (100.0 * 0.02 + 10.0 * 0.11)).abs() < 1e-4);
+        assert!((radiant - (100.0 * 0.1 + 10.0 * 0.2)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rank_by_ev_matches_every_tier() {
+        let items = items("Cheap Part,10\nGood Part,100");
+        let cheap = relic("Cheap Part", "Cheap Part", "Cheap Part");
+        let good = relic("Good Part", "Good Part", "Good Part");
+        let relics = Relics {
+            lith: HashMap::from([("A1".into(), cheap.clone())]),
+            meso: HashMap::from([("B2".into(), cheap.clone())]),
+            neo: HashMap::new(),
+            axi: HashMap::from([("A1".into(), good)]),
+        };
+
+        let (ranked, missing) =
+            relics.rank_by_ev(&["a1", "Meso B2", "Z9"], &items, Refinement::Intact);
+        let names = ranked
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["Axi A1", "Lith A1", "Meso B2"]);
+        assert_eq!(missing, ["Z9"]);
+    }
+
+    #[test]
+    fn garbled_names() {
+        let items = items(PARTS);
+        let cases = [
+            ("Ash Prime Neuroptics", Some("Ash Prime Neuroptics")),
+            ("Ash Prlme Neur0ptics", Some("Ash Prime Neuroptics")),
+            ("Ash Prime Systerns", Some("Ash Prime Systems")),
+            ("Braton Prlme Barrell", Some("Braton Prime Barrel")),
+            ("Kompressa Prime Reciever", Some("Kompressa Prime Receiver")),
+            ("  Forma   Blueprlnt ", Some("Forma Blueprint")),
+            ("Kuva", None),
+            // shares "Ash Prime" with the other parts, but isn't one of them
+            ("Ash Prime Chassis", None),
+        ];
+
+        for (ocr, expected) in cases {
+            let found = items.find_item(ocr).map(|item| item.name);
+            assert_eq!(found.as_deref(), expected, "{ocr:?}");
+        }
+    }
+
+    #[test]
+    fn garbled_part_types() {
+        // PARTS has the other Ash Prime parts
+        let items = items(&format!("{PARTS}\nAsh Prime Blueprint\nAsh Prime Chassis"));
+        let cases = [
+            ("Ash Prime Chasis", "Ash Prime Chassis"),
+            ("Ash Prlme Chasis", "Ash Prime Chassis"),
+            ("Ash Prime Systerns", "Ash Prime Systems"),
+            ("Ash Prime Neur0ptics", "Ash Prime Neuroptics"),
+            ("Ash Prime Blueprlnt", "Ash Prime Blueprint"),
+        ];
+
+        for (ocr, expected) in cases {
+            let found = items.find_item(ocr).map(|item| item.name);
+            assert_eq!(found.as_deref(), Some(expected), "{ocr:?}");
+        }
+    }
+
+    #[test]
+    fn sibling_parts_are_not_candidates() {
+        let items = items(PARTS);
+        let candidates = items.find_candidates("Ash Prime Neur0ptics", 3);
+        let names = candidates
+            .iter()
+            .map(|(item, _)| item.name.as_str())
+            .collect::<Vec<_>>();
+
+        // the other Ash Prime part shares two tokens, but not the part type
+        assert_eq!(names, ["Ash Prime Neuroptics"]);
+    }
+
+    #[test]
+    fn short_reward_names() {
+        let items = items("Fang Prime Blade,5\nForm Prime Blueprint,3\nKuva\nForma Blueprint");
+        let found = |ocr| items.find_item(ocr).map(|item| item.name);
+
+        assert_eq!(found("Forma Blueprint").as_deref(), Some("Forma Blueprint"));
+        assert_eq!(found("Kuva").as_deref(), Some("Kuva"));
+        // only the first line was read, the closest first token wins over the first item
+        assert_eq!(found("Forma").as_deref(), Some("Forma Blueprint"));
+    }
+
+    #[test]
+    fn near_miss_threshold() {
+        // a third of 6, 5 and 6 characters allows 2, 1 and 2 edits
+        let items = items("Braton Prime Barrel,5");
+        let at_threshold = "Brxxon Pxime Baxxel";
+        let past_threshold = "Bxxxon Pxxme Bxxxel";
+
+        assert!(items.find_item(at_threshold).is_some());
+        assert!(items.find_item(past_threshold).is_none());
+
+        // low confidence and a looser threshold both allow one more edit
+        assert!(
+            items
+                .find_item_with_confidence(past_threshold, Some(LOW_CONFIDENCE - 1.0))
+                .is_some()
+        );
+        let items = items.with_max_relative_distance(Some(0.5));
+        assert!(items.find_item(past_threshold).is_some());
+    }
+
+    #[test]
+    fn ducats_present_and_missing() {
+        let json = r#"{
+            "type": "Warframes",
+            "vaulted": true,
+            "parts": {
+                "Ash Prime Neuroptics": { "ducats": 45 },
+                "Ash Prime Systems": { "ducats": "65" },
+                "Ash Prime Chassis": { "ducats": { "value": 15 } },
+                "Ash Prime Blueprint": {},
+                "Ash Prime Set": { "ducats": null }
+            }
+        }"#;
+
+        let equipment = load_from_str::<EquipmentItem>(json).unwrap();
+        let ducats = |name: &str| equipment.parts[name].ducats;
+
+        assert_eq!(ducats("Ash Prime Neuroptics"), 45);
+        assert_eq!(ducats("Ash Prime Systems"), 65);
+        assert_eq!(ducats("Ash Prime Chassis"), 15);
+        assert_eq!(ducats("Ash Prime Blueprint"), 0);
+        assert_eq!(ducats("Ash Prime Set"), 0);
+    }
+
+    /// Answers each request on `listener` with the next of `statuses` and `body`
+    fn serve(listener: std::net::TcpListener, statuses: &'static [u16], body: &'static str) {
+        use std::io::{BufRead, Write};
+
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+
+                // skip the request, it's always a GET without a body
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let response = format!(
+                    "HTTP/1.1 {status} Status\r\n\
+                     Content-Type: application/json\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/prices", listener.local_addr().unwrap());
+        serve(
+            listener,
+            &[503, 500, 200],
+            r#"[{"name":"Forma","custom_avg":"1.5"}]"#,
+        );
+
+        let wfi = WfInfo::new().unwrap();
+        let prices = wfi.get_json::<PriceItems>(&url).await.unwrap();
+
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices[0].custom_avg, 1.5);
+    }
+
+    #[tokio::test]
+    async fn server_errors_fail_after_the_last_retry() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/prices", listener.local_addr().unwrap());
+        serve(listener, &[503, 503, 503, 200], "[]");
+
+        let wfi = WfInfo::new().unwrap();
+        let err = wfi.get_json::<PriceItems>(&url).await.unwrap_err();
+
+        assert!(matches!(err, crate::Error::Reqwest(err) if err.is_status()));
     }
 }
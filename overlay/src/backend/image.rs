@@ -1,23 +1,26 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 use femtovg::renderer::OpenGl;
-use femtovg::{Canvas, Color};
-use image::DynamicImage;
-use crate::backend::OverlayBackend;
+use femtovg::Color;
+use image::{DynamicImage, ImageFormat, RgbaImage, imageops};
+use crate::backend::{
+    EGL_DRIVER_HINT, EglErrorCode, OverlayBackend, create_canvas, egl_config_attribs
+};
 use crate::{Error, OverlayConf, OverlayRenderer, OverlayTime};
 
 #[derive(Error, Debug)]
 pub enum ImageError {
-    #[error("EGL display not found")]
-    EglDisplayNotFound,
-    #[error("EGL config not found")]
-    EglConfigNotFound,
-    #[error("EGL surface failed")]
-    EglSurfaceFailed,
-    #[error("EGL context not found")]
-    EglContextNotFound,
-    #[error("EGL bind api failed")]
-    EglBindApiFailed,
+    #[error("EGL display not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglDisplayNotFound(EglErrorCode),
+    #[error("EGL config not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglConfigNotFound(EglErrorCode),
+    #[error("EGL surface failed: {0}")]
+    EglSurfaceFailed(EglErrorCode),
+    #[error("EGL context not found: {0}")]
+    EglContextNotFound(EglErrorCode),
+    #[error("EGL bind api failed: {0}")]
+    EglBindApiFailed(EglErrorCode),
     #[error("Canvas failed to get image")]
     CanvasFailedGetImage,
     #[error("Image creation failed")]
@@ -30,53 +33,87 @@ pub enum ImageError {
     FailedToSave(PathBuf),
 }
 
+/// Crops the image to the bounding box of its non-transparent pixels,
+/// a fully transparent image becomes a single transparent pixel
+fn trim(image: RgbaImage) -> RgbaImage {
+    let bounds = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] != 0)
+        .fold(None, |bounds, (x, y, _)| match bounds {
+            None => Some((x, y, x, y)),
+            Some((min_x, min_y, max_x, max_y)) => {
+                Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+            }
+        });
+
+    let Some((min_x, min_y, max_x, max_y)) = bounds else {
+        return RgbaImage::new(1, 1);
+    };
+
+    imageops::crop_imm(&image, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1).to_image()
+}
+
+/// Saves to a temporary file next to `path` then renames it over `path`,
+/// so anything polling `path` never reads a half written image
+fn save_atomic(image: &DynamicImage, path: &Path) -> Result<(), ImageError> {
+    let failed = || ImageError::FailedToSave(path.to_owned());
+
+    let format = ImageFormat::from_path(path).map_err(|_| failed())?;
+    let file_name = path.file_name().ok_or_else(failed)?;
+
+    // same directory so the rename never crosses filesystems
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+
+    image
+        .save_with_format(&temp, format)
+        .map_err(|_| failed())?;
+
+    std::fs::rename(&temp, path).map_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+        failed()
+    })
+}
+
 #[derive(Default)]
 pub struct ImageBackend;
 
-impl OverlayBackend for ImageBackend {
-    type Renderer = OpenGl;
-
-    fn run(
+impl ImageBackend {
+    /// Renders `overlay` offscreen like [ImageBackend::run] but returns the image instead of
+    /// saving it, [OverlayConf::save_path] is ignored
+    pub fn render(
         &mut self,
         conf: OverlayConf,
-        mut overlay: impl OverlayRenderer<Self::Renderer>,
-    ) -> Result<(), Error> {
-        let Some(save_path) = &conf.save_path else {
-            return Err(Error::ImageError(ImageError::NoSavePath));
-        };
-
+        mut overlay: impl OverlayRenderer<OpenGl>,
+    ) -> Result<DynamicImage, Error> {
         unsafe {
             std::env::set_var("EGL_DRIVER", "swrast");
             std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
         }
 
-        let display =
-            egl::get_display(egl::EGL_DEFAULT_DISPLAY).ok_or(ImageError::EglDisplayNotFound)?;
+        let display = egl::get_display(egl::EGL_DEFAULT_DISPLAY)
+            .ok_or_else(|| ImageError::EglDisplayNotFound(EglErrorCode::last()))?;
 
         let mut major = 0;
         let mut minor = 0;
         egl::initialize(display, &mut major, &mut minor);
 
         if !egl::bind_api(egl::EGL_OPENGL_ES_API) {
-            return Err(Error::ImageError(ImageError::EglBindApiFailed));
+            return Err(Error::ImageError(ImageError::EglBindApiFailed(
+                EglErrorCode::last(),
+            )));
         }
 
-        #[rustfmt::skip]
-        let attribs = [
-            egl::EGL_RED_SIZE, 8,
-            egl::EGL_GREEN_SIZE, 8,
-            egl::EGL_BLUE_SIZE, 8,
-            egl::EGL_ALPHA_SIZE, 8,
-            egl::EGL_SURFACE_TYPE, egl::EGL_PBUFFER_BIT,
-            egl::EGL_NONE,
-        ];
+        let attribs = egl_config_attribs(egl::EGL_PBUFFER_BIT);
 
-        let config =
-            egl::choose_config(display, &attribs, 1).ok_or(ImageError::EglConfigNotFound)?;
+        let config = egl::choose_config(display, &attribs, 1)
+            .ok_or_else(|| ImageError::EglConfigNotFound(EglErrorCode::last()))?;
 
         let context_attribs = [egl::EGL_CONTEXT_CLIENT_VERSION, 2, egl::EGL_NONE];
         let context = egl::create_context(display, config, egl::EGL_NO_CONTEXT, &context_attribs)
-            .ok_or(ImageError::EglContextNotFound)?;
+            .ok_or_else(|| ImageError::EglContextNotFound(EglErrorCode::last()))?;
 
         #[rustfmt::skip]
         let surface_attrib = [
@@ -86,23 +123,18 @@ impl OverlayBackend for ImageBackend {
         ];
 
         let surface = egl::create_pbuffer_surface(display, config, &surface_attrib)
-            .ok_or(ImageError::EglSurfaceFailed)?;
+            .ok_or_else(|| ImageError::EglSurfaceFailed(EglErrorCode::last()))?;
 
         egl::make_current(display, surface, surface, context);
 
-        let renderer = unsafe {
-            gl::load_with(|symbol| egl::get_proc_address(symbol) as *const _);
-            OpenGl::new_from_function(|symbol| egl::get_proc_address(symbol) as *const _)?
-        };
+        gl::load_with(|symbol| egl::get_proc_address(symbol) as *const _);
 
-        let mut canvas = Canvas::new(renderer)?;
+        let mut canvas = create_canvas(conf.width, conf.height)?;
 
         let mut overlay_time = OverlayTime::new();
 
         overlay_time.update_delta();
 
-        canvas.set_size(conf.width, conf.height, 1.0);
-
         unsafe {
             gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
             gl::PixelStorei(gl::PACK_ROW_LENGTH, 0);
@@ -151,9 +183,11 @@ impl OverlayBackend for ImageBackend {
 
         let image = DynamicImage::ImageRgba8(image).flipv();
 
-        image
-            .save(save_path)
-            .map_err(|_| ImageError::FailedToSave(save_path.to_owned()))?;
+        let image = if conf.trim {
+            DynamicImage::ImageRgba8(trim(image.into_rgba8()))
+        } else {
+            image
+        };
 
         // drop
         drop(canvas);
@@ -167,6 +201,123 @@ impl OverlayBackend for ImageBackend {
         egl::destroy_surface(display, surface);
         egl::terminate(display);
 
+        Ok(image)
+    }
+}
+
+impl OverlayBackend for ImageBackend {
+    type Renderer = OpenGl;
+
+    fn run(
+        &mut self,
+        conf: OverlayConf,
+        overlay: impl OverlayRenderer<Self::Renderer>,
+    ) -> Result<(), Error> {
+        let Some(save_path) = conf.save_path.clone() else {
+            return Err(Error::ImageError(ImageError::NoSavePath));
+        };
+
+        let image = self.render(conf, overlay)?;
+        save_atomic(&image, &save_path)?;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    #[test]
+    fn trim_crops_to_content() {
+        let mut image = RgbaImage::new(64, 32);
+        image.put_pixel(10, 4, RED);
+        image.put_pixel(20, 12, RED);
+
+        assert_eq!(trim(image).dimensions(), (11, 9));
+        assert_eq!(trim(RgbaImage::new(64, 32)).dimensions(), (1, 1));
+    }
+
+    /// Renders through EGL, run with `cargo test -p overlay --features egl-tests`
+    #[cfg(feature = "egl-tests")]
+    mod egl {
+        use femtovg::{Canvas, Paint, Path};
+
+        use super::*;
+
+        const WIDTH: u32 = 64;
+        const HEIGHT: u32 = 32;
+        const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+
+        /// Pixel aligned rectangles, so nothing depends on anti-aliasing
+        struct Rects;
+
+        impl OverlayRenderer<OpenGl> for Rects {
+            fn draw(&mut self, canvas: &mut Canvas<OpenGl>, _: &OverlayTime) -> Result<(), Error> {
+                let mut path = Path::new();
+                path.rect(0.0, 0.0, 32.0, 32.0);
+                canvas.fill_path(&path, &Paint::color(Color::rgb(255, 0, 0)));
+
+                let mut path = Path::new();
+                path.rect(40.0, 8.0, 16.0, 16.0);
+                canvas.fill_path(&path, &Paint::color(Color::rgb(0, 255, 0)));
+
+                Ok(())
+            }
+        }
+
+        /// What [Rects] looks like on screen, the left half is red and a green square
+        /// floats on the transparent right half
+        fn reference() -> RgbaImage {
+            RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| match (x, y) {
+                (0..32, _) => RED,
+                (40..56, 8..24) => GREEN,
+                _ => Rgba([0, 0, 0, 0]),
+            })
+        }
+
+        fn assert_matches(image: &RgbaImage, reference: &RgbaImage) {
+            assert_eq!(image.dimensions(), reference.dimensions());
+
+            for ((x, y, pixel), expected) in image.enumerate_pixels().zip(reference.pixels()) {
+                let close = pixel
+                    .0
+                    .iter()
+                    .zip(expected.0)
+                    .all(|(a, b)| a.abs_diff(b) <= 2);
+                assert!(
+                    close,
+                    "pixel ({x}, {y}) is {pixel:?}, expected {expected:?}"
+                );
+            }
+        }
+
+        fn conf(trim: bool) -> OverlayConf {
+            OverlayConf {
+                width: WIDTH,
+                height: HEIGHT,
+                trim,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn headless_render_matches_reference() {
+            let image = ImageBackend.render(conf(false), Rects).unwrap();
+
+            assert_matches(&image.into_rgba8(), &reference());
+        }
+
+        #[test]
+        fn trimmed_render_matches_reference() {
+            let image = ImageBackend.render(conf(true), Rects).unwrap();
+            let reference = imageops::crop_imm(&reference(), 0, 0, 56, 32).to_image();
+
+            assert_matches(&image.into_rgba8(), &reference);
+        }
+    }
+}
@@ -1,11 +1,86 @@
+use std::fmt::{Display, Formatter};
+
 use femtovg::renderer::OpenGl;
-use femtovg::Renderer;
+use femtovg::{Canvas, Renderer};
 
 use crate::{Error, OverlayConf, OverlayRenderer};
 
+/// Shown when EGL can't find a display or config, which almost always means no driver is installed
+pub const EGL_DRIVER_HINT: &str = "no usable OpenGL/EGL driver found, \
+    install Mesa (mesa, libegl1 and libgl1-mesa-dri on Debian/Ubuntu), \
+    headless systems and containers need its software renderer (llvmpipe/swrast)";
+
+/// Error code from `eglGetError`, taken right after the call that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EglErrorCode(pub egl::EGLint);
+
+impl EglErrorCode {
+    pub fn last() -> Self {
+        Self(egl::get_error())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.0 {
+            egl::EGL_SUCCESS => "EGL_SUCCESS",
+            egl::EGL_NOT_INITIALIZED => "EGL_NOT_INITIALIZED",
+            egl::EGL_BAD_ACCESS => "EGL_BAD_ACCESS",
+            egl::EGL_BAD_ALLOC => "EGL_BAD_ALLOC",
+            egl::EGL_BAD_ATTRIBUTE => "EGL_BAD_ATTRIBUTE",
+            egl::EGL_BAD_CONFIG => "EGL_BAD_CONFIG",
+            egl::EGL_BAD_CONTEXT => "EGL_BAD_CONTEXT",
+            egl::EGL_BAD_CURRENT_SURFACE => "EGL_BAD_CURRENT_SURFACE",
+            egl::EGL_BAD_DISPLAY => "EGL_BAD_DISPLAY",
+            egl::EGL_BAD_MATCH => "EGL_BAD_MATCH",
+            egl::EGL_BAD_NATIVE_PIXMAP => "EGL_BAD_NATIVE_PIXMAP",
+            egl::EGL_BAD_NATIVE_WINDOW => "EGL_BAD_NATIVE_WINDOW",
+            egl::EGL_BAD_PARAMETER => "EGL_BAD_PARAMETER",
+            egl::EGL_BAD_SURFACE => "EGL_BAD_SURFACE",
+            egl::EGL_CONTEXT_LOST => "EGL_CONTEXT_LOST",
+            _ => "unknown EGL error",
+        }
+    }
+}
+
+impl Display for EglErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:#x})", self.name(), self.0)
+    }
+}
+
+/// EGL config attributes every backend uses, so `--output` renders the same as the live overlay
+///
+/// femtovg fills paths with the stencil buffer and doesn't need depth or multisampling
+#[rustfmt::skip]
+pub(crate) fn egl_config_attribs(surface_type: egl::EGLint) -> [egl::EGLint; 17] {
+    [
+        egl::EGL_RED_SIZE, 8,
+        egl::EGL_GREEN_SIZE, 8,
+        egl::EGL_BLUE_SIZE, 8,
+        egl::EGL_ALPHA_SIZE, 8,
+        egl::EGL_DEPTH_SIZE, 0,
+        egl::EGL_STENCIL_SIZE, 8,
+        egl::EGL_SAMPLE_BUFFERS, 0,
+        egl::EGL_SURFACE_TYPE, surface_type,
+        egl::EGL_NONE,
+    ]
+}
+
+/// Creates the canvas for the current EGL context, shared by every backend
+pub(crate) fn create_canvas(width: u32, height: u32) -> Result<Canvas<OpenGl>, Error> {
+    let renderer =
+        unsafe { OpenGl::new_from_function(|symbol| egl::get_proc_address(symbol) as *const _)? };
+
+    let mut canvas = Canvas::new(renderer)?;
+    canvas.set_size(width, height, 1.0);
+
+    Ok(canvas)
+}
+
 pub mod image;
 #[cfg(feature = "wayland")]
 pub mod wayland;
+#[cfg(feature = "x11")]
+pub mod x11;
 
 pub trait OverlayBackend {
     type Renderer: Renderer;
@@ -31,6 +106,8 @@ pub enum OverlayMethod {
 pub enum OverlayBackendImpl {
     #[cfg(feature = "wayland")]
     Wayland(wayland::WaylandOverlayBackend),
+    #[cfg(feature = "x11")]
+    X11(x11::X11OverlayBackend),
     Image(image::ImageBackend),
 }
 
@@ -45,6 +122,8 @@ impl OverlayBackend for OverlayBackendImpl {
         match self {
             #[cfg(feature = "wayland")]
             OverlayBackendImpl::Wayland(wayland) => wayland.run(conf, overlay),
+            #[cfg(feature = "x11")]
+            OverlayBackendImpl::X11(x11) => x11.run(conf, overlay),
             OverlayBackendImpl::Image(image) => image.run(conf, overlay),
         }
     }
@@ -57,7 +136,7 @@ pub fn get_backend(method: OverlayMethod) -> Option<OverlayBackendImpl> {
         #[cfg(not(feature = "wayland"))]
         OverlayMethod::Wayland => None,
         #[cfg(feature = "x11")]
-        OverlayMethod::X11 => None,
+        OverlayMethod::X11 => Some(OverlayBackendImpl::X11(x11::X11OverlayBackend)),
         #[cfg(not(feature = "x11"))]
         OverlayMethod::X11 => None,
         OverlayMethod::Image => Some(OverlayBackendImpl::Image(image::ImageBackend)),
@@ -1,8 +1,9 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use femtovg::renderer::OpenGl;
-use femtovg::{Canvas, Color};
+use femtovg::Color;
 use thiserror::Error;
 use wayland_client::backend::WaylandError as WaylandBackendError;
 use wayland_client::globals::{
@@ -11,16 +12,21 @@ use wayland_client::globals::{
 use wayland_client::protocol::wl_compositor::WlCompositor;
 use wayland_client::protocol::wl_keyboard;
 use wayland_client::protocol::wl_keyboard::WlKeyboard;
+use wayland_client::protocol::wl_output::{self, WlOutput};
 use wayland_client::protocol::wl_region::WlRegion;
 use wayland_client::protocol::wl_registry::WlRegistry;
 use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::wl_surface::WlSurface;
-use wayland_client::{ConnectError, Connection, Dispatch, DispatchError, Proxy, QueueHandle};
+use wayland_client::{
+    ConnectError, Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle
+};
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 use zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1};
 use zwlr_layer_surface_v1::{Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1};
 
-use crate::backend::OverlayBackend;
+use crate::backend::{
+    EGL_DRIVER_HINT, EglErrorCode, OverlayBackend, create_canvas, egl_config_attribs
+};
 use crate::{OverlayAnchor, OverlayConf, OverlayRenderer, OverlayTime};
 
 #[derive(Error, Debug)]
@@ -37,14 +43,14 @@ pub enum WaylandError {
     WaylandError(#[from] WaylandBackendError),
     #[error(transparent)]
     WaylandEglError(#[from] wayland_egl::Error),
-    #[error("EGL display not found")]
-    EglDisplayNotFound,
-    #[error("EGL config not found")]
-    EglConfigNotFound,
-    #[error("EGL surface not found")]
-    EglSurfaceNotFound,
-    #[error("EGL context not found")]
-    EglContextNotFound,
+    #[error("EGL display not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglDisplayNotFound(EglErrorCode),
+    #[error("EGL config not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglConfigNotFound(EglErrorCode),
+    #[error("EGL surface not found: {0}")]
+    EglSurfaceNotFound(EglErrorCode),
+    #[error("EGL context not found: {0}")]
+    EglContextNotFound(EglErrorCode),
 }
 
 #[allow(dead_code)]
@@ -67,6 +73,58 @@ impl From<OverlayAnchor> for Anchor {
     }
 }
 
+/// Output names need wl_output version 4
+const WL_OUTPUT_VERSION: u32 = 4;
+
+/// Binds every output and waits for their names, like `DP-1` or `HDMI-A-1`
+///
+/// outputs from compositors older than wl_output version 4 have no name and are left out
+fn bind_outputs(
+    globals: &GlobalList,
+    event_queue: &mut EventQueue<WlState>,
+    state: &mut WlState,
+) -> Result<Vec<(WlOutput, String)>, WaylandError> {
+    let qh = event_queue.handle();
+
+    let outputs = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+            .map(|global| {
+                let version = global.version.min(WL_OUTPUT_VERSION);
+                let output = globals.registry().bind::<WlOutput, _, _>(
+                    global.name,
+                    version,
+                    &qh,
+                    global.name,
+                );
+
+                (global.name, output)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    event_queue.roundtrip(state)?;
+
+    let outputs = outputs
+        .into_iter()
+        .filter_map(|(global, output)| Some((output, state.output_names.get(&global)?.clone())))
+        .collect();
+
+    Ok(outputs)
+}
+
+/// Names of every output, for [OverlayConf::output]
+pub fn list_outputs() -> Result<Vec<String>, crate::Error> {
+    let conn = Connection::connect_to_env().map_err(WaylandError::from)?;
+    let (globals, mut event_queue) =
+        registry_queue_init::<WlState>(&conn).map_err(WaylandError::from)?;
+
+    let mut state = WlState::new(Arc::default());
+    let outputs = bind_outputs(&globals, &mut event_queue, &mut state)?;
+
+    Ok(outputs.into_iter().map(|(_, name)| name).collect())
+}
+
 impl WaylandOverlayBackend {
     #[allow(dead_code)]
     fn run_impl(
@@ -76,6 +134,12 @@ impl WaylandOverlayBackend {
     ) -> Result<(), crate::Error> {
         log::debug!("Starting Wayland overlay");
 
+        if let Some(position) = conf.position {
+            log::debug!(
+                "Ignoring overlay position {position:?}, wayland only supports anchor and margin"
+            );
+        }
+
         conf.close_handle.store(false, Ordering::SeqCst);
 
         // Wayland Impl
@@ -86,6 +150,25 @@ impl WaylandOverlayBackend {
             registry_queue_init::<WlState>(&conn).map_err(WaylandError::from)?;
         let qh = event_queue.handle();
 
+        let mut state = WlState::new(conf.close_handle.clone());
+
+        let output = match &conf.output {
+            Some(name) => {
+                let outputs = bind_outputs(&globals, &mut event_queue, &mut state)?;
+                let output = outputs.iter().find(|(_, output)| output == name);
+
+                if output.is_none() {
+                    let names = outputs.iter().map(|(_, name)| name).collect::<Vec<_>>();
+                    log::warn!(
+                        "Output {name} not found, letting the compositor pick, outputs: {names:?}"
+                    );
+                }
+
+                output.map(|(output, _)| output.clone())
+            }
+            None => None,
+        };
+
         let compositor = globals
             .bind::<WlCompositor, _, _>(&qh, 1..=4, ())
             .map_err(WaylandError::from)?;
@@ -103,7 +186,7 @@ impl WaylandOverlayBackend {
 
         let layer_surface = layer_shell.get_layer_surface(
             &wl_surface,
-            None,
+            output.as_ref(),
             Layer::Overlay,
             "overlay".into(),
             &qh,
@@ -112,7 +195,11 @@ impl WaylandOverlayBackend {
 
         layer_surface.set_size(conf.width, conf.height);
         layer_surface.set_exclusive_zone(-1);
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+        layer_surface.set_keyboard_interactivity(if conf.interactive {
+            KeyboardInteractivity::OnDemand
+        } else {
+            KeyboardInteractivity::None
+        });
 
         let (top, right, bottom, left) = conf.margin.into();
 
@@ -121,8 +208,11 @@ impl WaylandOverlayBackend {
 
         // layer_surface.set_anchor(Anchor::Bottom | Anchor::Top | Anchor::Left | Anchor::Right);
 
-        // let region = compositor.create_region(&qh, ());
-        // wl_surface.set_input_region(Some(&region));
+        if !conf.interactive {
+            // empty input region lets clicks pass through to the game
+            let region = compositor.create_region(&qh, ());
+            wl_surface.set_input_region(Some(&region));
+        }
 
         wl_surface.commit();
 
@@ -135,28 +225,21 @@ impl WaylandOverlayBackend {
         let egl_native_display_type = backend.display_ptr() as _;
         let egl_native_window_type = wl_egl_surface.ptr() as _;
 
-        let egl_display =
-            egl::get_display(egl_native_display_type).ok_or(WaylandError::EglDisplayNotFound)?;
+        let egl_display = egl::get_display(egl_native_display_type)
+            .ok_or_else(|| WaylandError::EglDisplayNotFound(EglErrorCode::last()))?;
 
         let mut major = 0;
         let mut minor = 0;
         egl::initialize(egl_display, &mut major, &mut minor);
 
-        #[rustfmt::skip]
-        let attribs = [
-            egl::EGL_RED_SIZE, 8,
-            egl::EGL_GREEN_SIZE, 8,
-            egl::EGL_BLUE_SIZE, 8,
-            egl::EGL_ALPHA_SIZE, 8,
-            egl::EGL_NONE,
-        ];
+        let attribs = egl_config_attribs(egl::EGL_WINDOW_BIT);
 
-        let egl_config =
-            egl::choose_config(egl_display, &attribs, 1).ok_or(WaylandError::EglSurfaceNotFound)?;
+        let egl_config = egl::choose_config(egl_display, &attribs, 1)
+            .ok_or_else(|| WaylandError::EglConfigNotFound(EglErrorCode::last()))?;
 
         let egl_surface =
             egl::create_window_surface(egl_display, egl_config, egl_native_window_type, &[])
-                .ok_or(WaylandError::EglSurfaceNotFound)?;
+                .ok_or_else(|| WaylandError::EglSurfaceNotFound(EglErrorCode::last()))?;
 
         let context_attribs = [egl::EGL_CONTEXT_CLIENT_VERSION, 2, egl::EGL_NONE];
         let egl_context = egl::create_context(
@@ -165,39 +248,39 @@ impl WaylandOverlayBackend {
             std::ptr::null_mut(),
             &context_attribs,
         )
-        .ok_or(WaylandError::EglContextNotFound)?;
+        .ok_or_else(|| WaylandError::EglContextNotFound(EglErrorCode::last()))?;
 
         egl::make_current(egl_display, egl_surface, egl_surface, egl_context);
 
-        let renderer = unsafe {
-            OpenGl::new_from_function(|symbol| egl::get_proc_address(symbol) as *const _)?
-        };
-
         // Canvas Impl
 
-        let mut canvas = Canvas::new(renderer)?;
+        let mut canvas = create_canvas(conf.width, conf.height)?;
 
         let mut overlay_time = OverlayTime::new();
 
-        canvas.set_size(conf.width, conf.height, 1.0);
-
         overlay.setup(&mut canvas, &overlay_time)?;
 
-        let mut state = WlState {
-            close_token: conf.close_handle.clone(),
-        };
-
         loop {
             event_queue
                 .dispatch_pending(&mut state)
                 .map_err(WaylandError::from)?;
 
-            if conf.close_handle.load(Ordering::SeqCst) {
+            if overlay_time.closing.is_none() && conf.timed_out(&overlay_time) {
+                log::debug!("overlay timed out");
+                conf.close_handle.store(true, Ordering::SeqCst);
+            }
+
+            if conf.close_handle.load(Ordering::SeqCst) && overlay_time.closing.is_none() {
                 log::debug!("closing overlay");
+                overlay_time.close();
+            }
+
+            if conf.faded_out(&overlay_time) {
                 break;
             }
 
             overlay_time.update_delta();
+            overlay_time.opacity = conf.opacity(&overlay_time);
 
             canvas.clear_rect(
                 0,
@@ -258,6 +341,17 @@ impl OverlayBackend for WaylandOverlayBackend {
 #[allow(dead_code)]
 struct WlState {
     close_token: Arc<AtomicBool>,
+    /// Output names by global name, see [bind_outputs]
+    output_names: HashMap<u32, String>,
+}
+
+impl WlState {
+    fn new(close_token: Arc<AtomicBool>) -> Self {
+        Self {
+            close_token,
+            output_names: HashMap::new(),
+        }
+    }
 }
 
 impl Dispatch<WlKeyboard, (), WlState> for WlState {
@@ -295,6 +389,21 @@ impl Dispatch<WlSeat, (), WlState> for WlState {
     }
 }
 
+impl Dispatch<WlOutput, u32, WlState> for WlState {
+    fn event(
+        state: &mut WlState,
+        _proxy: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        global: &u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<WlState>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            state.output_names.insert(*global, name);
+        }
+    }
+}
+
 impl Dispatch<WlRegion, (), WlState> for WlState {
     fn event(
         _state: &mut WlState,
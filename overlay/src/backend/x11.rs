@@ -0,0 +1,265 @@
+use std::sync::atomic::Ordering;
+
+use femtovg::renderer::OpenGl;
+use femtovg::Color;
+use thiserror::Error;
+use x11rb::CURRENT_TIME;
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectError, ConnectionError, ReplyOrIdError};
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{
+    ColormapAlloc, ConnectionExt, CreateWindowAux, EventMask, InputFocus, Screen, WindowClass
+};
+use x11rb::rust_connection::RustConnection;
+
+use crate::backend::{
+    EGL_DRIVER_HINT, EglErrorCode, OverlayBackend, create_canvas, egl_config_attribs
+};
+use crate::{OverlayAnchor, OverlayConf, OverlayRenderer, OverlayTime};
+
+#[derive(Error, Debug)]
+pub enum X11Error {
+    #[error(transparent)]
+    ConnectError(#[from] ConnectError),
+    #[error(transparent)]
+    ConnectionError(#[from] ConnectionError),
+    #[error(transparent)]
+    ReplyOrIdError(#[from] ReplyOrIdError),
+    #[error("No X11 depth has visual {0:#x}")]
+    VisualNotFound(u32),
+    #[error("EGL display not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglDisplayNotFound(EglErrorCode),
+    #[error("EGL config not found: {0}, {hint}", hint = EGL_DRIVER_HINT)]
+    EglConfigNotFound(EglErrorCode),
+    #[error("EGL surface not found: {0}")]
+    EglSurfaceNotFound(EglErrorCode),
+    #[error("EGL context not found: {0}")]
+    EglContextNotFound(EglErrorCode),
+}
+
+#[derive(Default)]
+pub struct X11OverlayBackend;
+
+/// Top left corner of the overlay on `screen`, from [OverlayConf::position] or its anchor and margin
+fn window_position(conf: &OverlayConf, screen: &Screen) -> (i32, i32) {
+    if let Some(position) = conf.position {
+        return position;
+    }
+
+    let screen_width = screen.width_in_pixels as i32;
+    let screen_height = screen.height_in_pixels as i32;
+    let width = conf.width as i32;
+    let height = conf.height as i32;
+    let margin = conf.margin;
+
+    let x = match conf.anchor {
+        OverlayAnchor::TopLeft | OverlayAnchor::CenterLeft | OverlayAnchor::BottomLeft => {
+            margin.left
+        }
+        OverlayAnchor::TopRight | OverlayAnchor::CenterRight | OverlayAnchor::BottomRight => {
+            screen_width - width - margin.right
+        }
+        _ => (screen_width - width) / 2 + margin.left - margin.right,
+    };
+
+    let y = if conf.anchor.is_top() {
+        margin.top
+    } else if conf.anchor.is_bottom() {
+        screen_height - height - margin.bottom
+    } else {
+        (screen_height - height) / 2 + margin.top - margin.bottom
+    };
+
+    (x, y)
+}
+
+impl X11OverlayBackend {
+    fn run_impl(
+        &mut self,
+        conf: OverlayConf,
+        mut overlay: impl OverlayRenderer<OpenGl>,
+    ) -> Result<(), crate::Error> {
+        log::debug!("Starting X11 overlay");
+
+        conf.close_handle.store(false, Ordering::SeqCst);
+
+        // EGL connects to the same X server through DISPLAY,
+        // so the config is picked first and the window is created with its visual
+        let egl_display = egl::get_display(egl::EGL_DEFAULT_DISPLAY)
+            .ok_or_else(|| X11Error::EglDisplayNotFound(EglErrorCode::last()))?;
+
+        let mut major = 0;
+        let mut minor = 0;
+        egl::initialize(egl_display, &mut major, &mut minor);
+
+        let attribs = egl_config_attribs(egl::EGL_WINDOW_BIT);
+
+        let egl_config = egl::choose_config(egl_display, &attribs, 1)
+            .ok_or_else(|| X11Error::EglConfigNotFound(EglErrorCode::last()))?;
+
+        let mut visual_id = 0;
+        egl::get_config_attrib(
+            egl_display,
+            egl_config,
+            egl::EGL_NATIVE_VISUAL_ID,
+            &mut visual_id,
+        );
+
+        // X11 Impl
+        let (conn, screen_num) = RustConnection::connect(None).map_err(X11Error::from)?;
+        let screen = &conn.setup().roots[screen_num];
+
+        let visual_id = visual_id as u32;
+        let depth = screen
+            .allowed_depths
+            .iter()
+            .find(|depth| depth.visuals.iter().any(|v| v.visual_id == visual_id))
+            .map(|depth| depth.depth)
+            .ok_or(X11Error::VisualNotFound(visual_id))?;
+
+        let (x, y) = window_position(&conf, screen);
+
+        let colormap = conn.generate_id().map_err(X11Error::from)?;
+        conn.create_colormap(ColormapAlloc::NONE, colormap, screen.root, visual_id)
+            .map_err(X11Error::from)?;
+
+        let window = conn.generate_id().map_err(X11Error::from)?;
+
+        // override redirect keeps the window manager from decorating, moving or focusing it
+        let window_aux = CreateWindowAux::new()
+            .override_redirect(1)
+            .background_pixel(0)
+            .border_pixel(0)
+            .colormap(colormap)
+            .event_mask(EventMask::KEY_PRESS | EventMask::BUTTON_PRESS);
+
+        conn.create_window(
+            depth,
+            window,
+            screen.root,
+            x as _,
+            y as _,
+            conf.width as _,
+            conf.height as _,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            visual_id,
+            &window_aux,
+        )
+        .map_err(X11Error::from)?;
+
+        conn.map_window(window).map_err(X11Error::from)?;
+        conn.flush().map_err(X11Error::from)?;
+
+        // X11 EGL Impl
+
+        let egl_native_window_type = window as usize as _;
+
+        let egl_surface =
+            egl::create_window_surface(egl_display, egl_config, egl_native_window_type, &[])
+                .ok_or_else(|| X11Error::EglSurfaceNotFound(EglErrorCode::last()))?;
+
+        let context_attribs = [egl::EGL_CONTEXT_CLIENT_VERSION, 2, egl::EGL_NONE];
+        let egl_context = egl::create_context(
+            egl_display,
+            egl_config,
+            egl::EGL_NO_CONTEXT,
+            &context_attribs,
+        )
+        .ok_or_else(|| X11Error::EglContextNotFound(EglErrorCode::last()))?;
+
+        egl::make_current(egl_display, egl_surface, egl_surface, egl_context);
+
+        // Canvas Impl
+
+        let mut canvas = create_canvas(conf.width, conf.height)?;
+
+        let mut overlay_time = OverlayTime::new();
+
+        overlay.setup(&mut canvas, &overlay_time)?;
+
+        loop {
+            while let Some(event) = conn.poll_for_event().map_err(X11Error::from)? {
+                match event {
+                    // like wayland's on demand keyboard, keys only arrive after clicking the overlay
+                    Event::ButtonPress(_) => {
+                        conn.set_input_focus(InputFocus::PARENT, window, CURRENT_TIME)
+                            .map_err(X11Error::from)?;
+                        conn.flush().map_err(X11Error::from)?;
+                    }
+                    Event::KeyPress(_) => {
+                        conf.close_handle.store(true, Ordering::SeqCst);
+                    }
+                    _ => {}
+                }
+            }
+
+            if overlay_time.closing.is_none() && conf.timed_out(&overlay_time) {
+                log::debug!("overlay timed out");
+                conf.close_handle.store(true, Ordering::SeqCst);
+            }
+
+            if conf.close_handle.load(Ordering::SeqCst) && overlay_time.closing.is_none() {
+                log::debug!("closing overlay");
+                overlay_time.close();
+            }
+
+            if conf.faded_out(&overlay_time) {
+                break;
+            }
+
+            overlay_time.update_delta();
+            overlay_time.opacity = conf.opacity(&overlay_time);
+
+            canvas.clear_rect(
+                0,
+                0,
+                canvas.width(),
+                canvas.height(),
+                Color::rgba(0, 0, 0, 0),
+            );
+
+            overlay.draw(&mut canvas, &overlay_time)?;
+
+            canvas.flush();
+
+            overlay_time.update_previous();
+
+            egl::swap_buffers(egl_display, egl_surface);
+        }
+
+        log::debug!("cleaning up");
+
+        // Drop any loose-ends
+        drop(canvas);
+        egl::make_current(
+            egl_display,
+            egl::EGL_NO_SURFACE,
+            egl::EGL_NO_SURFACE,
+            egl::EGL_NO_CONTEXT,
+        );
+        egl::destroy_context(egl_display, egl_context);
+        egl::destroy_surface(egl_display, egl_surface);
+        egl::terminate(egl_display);
+
+        conn.destroy_window(window).map_err(X11Error::from)?;
+        conn.free_colormap(colormap).map_err(X11Error::from)?;
+        conn.flush().map_err(X11Error::from)?;
+
+        conf.close_handle.store(false, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+impl OverlayBackend for X11OverlayBackend {
+    type Renderer = OpenGl;
+
+    fn run(
+        &mut self,
+        conf: OverlayConf,
+        overlay: impl OverlayRenderer<Self::Renderer>,
+    ) -> Result<(), crate::Error> {
+        self.run_impl(conf, overlay)
+    }
+}
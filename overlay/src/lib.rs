@@ -1,12 +1,14 @@
 pub mod backend;
 
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::time::{Duration, Instant};
 
 pub use femtovg;
-use femtovg::{Canvas, Paint, Renderer, TextMetrics};
+use femtovg::{Canvas, FontId, Paint, Renderer, TextMetrics};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,10 +16,67 @@ pub enum Error {
     #[cfg(feature = "wayland")]
     #[error(transparent)]
     WaylandError(#[from] backend::wayland::WaylandError),
+    #[cfg(feature = "x11")]
+    #[error(transparent)]
+    X11Error(#[from] backend::x11::X11Error),
     #[error(transparent)]
     ImageError(#[from] backend::image::ImageError),
     #[error(transparent)]
     FemtovgError(#[from] femtovg::ErrorKind),
+    #[error("No font could be loaded, install DejaVu Sans or set a font path")]
+    FontNotFound,
+}
+
+/// Where common distros install a sans font, tried after the configured font
+pub const FALLBACK_FONT_PATHS: &[&str] = &[
+    // Arch
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    // Debian, Ubuntu
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    // Fedora
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    // openSUSE
+    "/usr/share/fonts/truetype/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+];
+
+/// Loads `font` if set, then the first of [FALLBACK_FONT_PATHS] that loads,
+/// then whatever fontconfig resolves `sans-serif` to
+pub fn load_font<T: Renderer>(
+    canvas: &mut Canvas<T>,
+    font: Option<&Path>,
+) -> Result<FontId, Error> {
+    if let Some(font) = font {
+        match canvas.add_font(font) {
+            Ok(id) => return Ok(id),
+            Err(err) => log::warn!("Failed to load font {}: {err:?}", font.display()),
+        }
+    }
+
+    for path in FALLBACK_FONT_PATHS {
+        if let Ok(id) = canvas.add_font(path) {
+            log::debug!("Loaded font {path}");
+            return Ok(id);
+        }
+    }
+
+    // NixOS and others don't put fonts in fixed paths
+    let fontconfig = Command::new("fc-match")
+        .args(["--format=%{file}", "sans-serif"])
+        .output();
+
+    if let Ok(output) = fontconfig
+        && let Ok(path) = String::from_utf8(output.stdout)
+        && !path.is_empty()
+        && let Ok(id) = canvas.add_font(&path)
+    {
+        log::debug!("Loaded font {path} from fontconfig");
+        return Ok(id);
+    }
+
+    Err(Error::FontNotFound)
 }
 
 pub trait OverlayRenderer<T: Renderer> {
@@ -34,6 +93,10 @@ pub struct OverlayTime {
     pub start: Instant,
     pub previous: Duration,
     pub delta: Duration,
+    /// When closing was requested, the overlay keeps drawing until it has faded out
+    pub closing: Option<Instant>,
+    /// Set by live backends from [OverlayConf::opacity] every frame
+    pub opacity: f32,
 }
 
 impl Default for OverlayTime {
@@ -48,6 +111,8 @@ impl OverlayTime {
             start: Instant::now(),
             previous: Duration::default(),
             delta: Duration::default(),
+            closing: None,
+            opacity: 1.0,
         }
     }
 
@@ -58,6 +123,11 @@ impl OverlayTime {
     pub fn update_previous(&mut self) {
         self.previous = self.start.elapsed();
     }
+
+    /// Starts fading out, does nothing if it already started
+    pub fn close(&mut self) {
+        self.closing.get_or_insert_with(Instant::now);
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -101,10 +171,65 @@ pub struct OverlayConf {
     pub width: u32,
     pub height: u32,
     pub save_path: Option<PathBuf>,
+    /// Crops the saved image to its non-transparent content, only used with [Self::save_path]
+    pub trim: bool,
+    /// Absolute screen position of the top left corner, overrides anchor and margin
+    ///
+    /// only X11 can place windows globally, wayland falls back to anchor and margin
+    pub position: Option<(i32, i32)>,
+    /// Name of the output the overlay is shown on, like `DP-1`,
+    /// see `list_outputs` in the wayland backend
+    ///
+    /// only wayland picks outputs by name, X11 uses [Self::position]
+    pub output: Option<String>,
+    /// Closes the overlay after it has been shown this long
+    pub timeout: Option<Duration>,
+    /// How long the overlay takes to fade in after it opens
+    pub fade_in: Option<Duration>,
+    /// How long the overlay takes to fade out after closing is requested
+    pub fade_out: Option<Duration>,
+    /// Whether the overlay takes keyboard focus and pointer input,
+    /// any key closes it when true
+    ///
+    /// only wayland can make the overlay click-through, X11 always receives input
+    pub interactive: bool,
     #[serde(skip)]
     pub close_handle: Arc<AtomicBool>,
 }
 
+impl OverlayConf {
+    pub fn timed_out(&self, time: &OverlayTime) -> bool {
+        self.timeout
+            .is_some_and(|timeout| time.start.elapsed() >= timeout)
+    }
+
+    /// Opacity from 0.0 to 1.0 for the current frame, from [Self::fade_in] and [Self::fade_out]
+    pub fn opacity(&self, time: &OverlayTime) -> f32 {
+        let fade_in = match self.fade_in {
+            Some(fade_in) if !fade_in.is_zero() => {
+                time.start.elapsed().as_secs_f32() / fade_in.as_secs_f32()
+            }
+            _ => 1.0,
+        };
+
+        let fade_out = match (self.fade_out, time.closing) {
+            (Some(fade_out), Some(closing)) if !fade_out.is_zero() => {
+                1.0 - closing.elapsed().as_secs_f32() / fade_out.as_secs_f32()
+            }
+            (_, Some(_)) => 0.0,
+            _ => 1.0,
+        };
+
+        fade_in.min(fade_out).clamp(0.0, 1.0)
+    }
+
+    /// Whether closing was requested and the fade out has finished
+    pub fn faded_out(&self, time: &OverlayTime) -> bool {
+        time.closing
+            .is_some_and(|closing| closing.elapsed() >= self.fade_out.unwrap_or_default())
+    }
+}
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(from = "[i32; 4]", into = "[i32; 4]"))]
@@ -208,6 +333,14 @@ pub trait CanvasExt {
         fill_paint: &Paint,
         stroke_paint: Option<&Paint>,
     ) -> Result<TextMetrics, femtovg::ErrorKind>;
+
+    /// Truncates `text` with an ellipsis until it fits in `max_width`
+    fn fit_text<'a>(
+        &self,
+        text: &'a str,
+        paint: &Paint,
+        max_width: f32,
+    ) -> Result<Cow<'a, str>, femtovg::ErrorKind>;
 }
 
 impl<T: Renderer> CanvasExt for Canvas<T> {
@@ -229,4 +362,62 @@ impl<T: Renderer> CanvasExt for Canvas<T> {
 
         self.measure_text(x, y, text, fill_paint)
     }
+
+    fn fit_text<'a>(
+        &self,
+        text: &'a str,
+        paint: &Paint,
+        max_width: f32,
+    ) -> Result<Cow<'a, str>, femtovg::ErrorKind> {
+        fit_text_by(text, max_width, |text| {
+            Ok(self.measure_text(0.0, 0.0, text, paint)?.width())
+        })
+    }
+}
+
+/// [CanvasExt::fit_text] with `measure` giving the width of a text
+fn fit_text_by<'a, E>(
+    text: &'a str,
+    max_width: f32,
+    measure: impl Fn(&str) -> Result<f32, E>,
+) -> Result<Cow<'a, str>, E> {
+    if measure(text)? <= max_width {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    for (i, _) in text.char_indices().rev() {
+        let truncated = format!("{}…", text[..i].trim_end());
+
+        if measure(&truncated)? <= max_width {
+            return Ok(Cow::Owned(truncated));
+        }
+    }
+
+    Ok(Cow::Borrowed("…"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    /// Every character is 10 pixels wide
+    fn fit(text: &str, max_width: f32) -> Cow<'_, str> {
+        fit_text_by(text, max_width, |text| {
+            Ok::<_, Infallible>(text.chars().count() as f32 * 10.0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let name = "Kompressa Prime Receiver";
+
+        assert!(matches!(fit(name, 240.0), Cow::Borrowed(fitted) if fitted == name));
+        assert_eq!(fit(name, 90.0), "Kompress…");
+        // trailing spaces are dropped before the ellipsis
+        assert_eq!(fit(name, 110.0), "Kompressa…");
+        assert_eq!(fit(name, 5.0), "…");
+    }
 }
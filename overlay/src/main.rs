@@ -9,7 +9,7 @@ struct Overlay;
 
 impl<T: Renderer> OverlayRenderer<T> for Overlay {
     fn setup(&mut self, canvas: &mut Canvas<T>, _: &OverlayTime) -> Result<(), Error> {
-        canvas.add_font("/usr/share/fonts/TTF/DejaVuSans.ttf")?;
+        overlay::load_font(canvas, None)?;
         Ok(())
     }
 
@@ -49,6 +49,13 @@ fn main() -> Result<(), overlay::Error> {
         width: 1200,
         height: 200,
         save_path: Some("test.png".into()),
+        trim: false,
+        position: None,
+        output: None,
+        timeout: None,
+        fade_in: None,
+        fade_out: None,
+        interactive: true,
         close_handle: close_handle.clone(),
     };
 
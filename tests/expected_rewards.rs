@@ -0,0 +1,82 @@
+//! Runs the OCR pipeline over the screenshots in `test-images/rewards` and checks every reward
+//! against `expected.json`, the same fixture `examples/pipeline_bench.rs` scores
+//!
+//! skipped when the screenshots aren't checked out
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use lib::ocr::{OcrMode, Substitutions, reward_image_to_items};
+use lib::theme::ThemeSelection;
+use lib::timings::Timings;
+use lib::util::RewardLayout;
+use lib::wfinfo::{Items, normalize_part_name};
+
+const REWARDS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test-images/rewards");
+
+/// `items.csv` next to the screenshots when there is one, so matching sees every item
+/// like it does live, otherwise only the expected rewards
+fn items(dir: &Path, expected: &BTreeMap<String, Vec<Option<String>>>) -> Items {
+    if let Ok(file) = std::fs::File::open(dir.join("items.csv")) {
+        return Items::from_csv(file, ',').unwrap();
+    }
+
+    let csv = expected
+        .values()
+        .flatten()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Items::from_csv(csv.as_bytes(), ',').unwrap()
+}
+
+#[test]
+fn rewards_match_expected() {
+    let dir = Path::new(REWARDS_DIR);
+
+    let Ok(file) = std::fs::File::open(dir.join("expected.json")) else {
+        eprintln!("skipped, no expected.json in {REWARDS_DIR}");
+        return;
+    };
+
+    let expected: BTreeMap<String, Vec<Option<String>>> = serde_json::from_reader(file).unwrap();
+    let items = items(dir, &expected);
+    let substitutions = Substitutions::default();
+
+    let mut mismatches = vec![];
+
+    for (file, expected) in &expected {
+        let image = image::open(dir.join(file)).unwrap();
+
+        let (found, _) = reward_image_to_items(
+            &items,
+            image,
+            ThemeSelection::default(),
+            &RewardLayout::default(),
+            OcrMode::default(),
+            &substitutions,
+            None,
+            &mut Timings::default(),
+        )
+        .unwrap();
+
+        let found = found
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.map(|item| item.name))
+            .collect::<Vec<_>>();
+
+        let expected = expected
+            .iter()
+            .map(|name| name.as_deref().map(normalize_part_name))
+            .collect::<Vec<_>>();
+
+        if found != expected {
+            mismatches.push(format!("{file}: expected {expected:?}, found {found:?}"));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}